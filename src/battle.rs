@@ -0,0 +1,292 @@
+use crate::{combat::*, defs::*, durability::*, game::*, impls::*, stats::*};
+use rand::Rng;
+
+/// armor each level of a wall gives the defenders of its stronghold
+pub const WALL_COVER: u32 = 2;
+
+/// damage each level of a tower adds to the defenders' hits
+pub const TOWER_VOLLEY: u32 = 2;
+
+/// items each surviving raider carries off from a stronghold it sacks
+pub const LOOT_PER_RAIDER: u32 = 5;
+
+/// chance a combatant breaks and runs after its side takes losses in an exchange. the more of its side is down and the less loyal it is, the likelier
+pub fn rout_chance(loyalty: AttributeLevel, losses: f32) -> f32 {
+    losses * (6 - loyalty as i32) as f32 / 10.0
+}
+
+/// cover and volley a stronghold's walls and towers give its defenders, counted as its defence is
+pub fn fortified(stronghold: &Stronghold) -> (i32, i32) {
+    (
+        walls_and_towers(stronghold, WALL_COVER, 0) as i32,
+        walls_and_towers(stronghold, 0, TOWER_VOLLEY) as i32,
+    )
+}
+
+/// rough fighting strength of a force, for sizing up a fight: what each fighter still standing hits for times the health it has left, with any cover and volley from walls and towers
+pub fn fighting_strength(combatants: &[Combatant], cover: i32, volley: i32) -> i32 {
+    combatants
+        .iter()
+        .filter(|combatant| combatant.standing())
+        .map(|combatant| (combatant.damage() + volley) * (combatant.health + cover))
+        .sum()
+}
+
+/// up to `count` of a stronghold's warriors, best first, with their equipment
+pub fn muster(stronghold: &Stronghold, side: Side, count: usize) -> Vec<Combatant> {
+    let mut warriors = stronghold
+        .elves
+        .iter()
+        .filter_map(|elf| role_ability(elf, &Role::Warrior).map(|ability| (ability, elf)))
+        .collect::<Vec<(AttributeLevel, &Elf)>>();
+
+    warriors.sort_by_key(|(ability, _)| std::cmp::Reverse(*ability as i32));
+
+    warriors
+        .into_iter()
+        .take(count)
+        .map(|(_, elf)| Combatant::elf(stronghold, elf, side))
+        .collect()
+}
+
+/// everyone who turns out to defend a stronghold: its warriors, or every elf if it has none
+pub fn defenders(stronghold: &Stronghold) -> Vec<Combatant> {
+    let warriors = muster(stronghold, Side::Defenders, usize::MAX);
+
+    if !warriors.is_empty() {
+        return warriors;
+    }
+
+    stronghold
+        .elves
+        .iter()
+        .map(|elf| Combatant::elf(stronghold, elf, Side::Defenders))
+        .collect()
+}
+
+impl Encounter {
+    /// the enemy a fighter goes for: whoever the fewest are already attacking this exchange, then the most wounded
+    pub fn target(&self, side: Side, engaged: &[u32]) -> Option<usize> {
+        (0..self.combatants.len())
+            .filter(|&i| self.combatants[i].side == side && self.combatants[i].standing())
+            .min_by_key(|&i| (engaged[i], self.combatants[i].health))
+    }
+
+    /// combatants of a side no longer fighting
+    fn down(&self, side: Side) -> usize {
+        self.combatants.iter().filter(|c| c.side == side && !c.standing()).count()
+    }
+
+    /// fight one exchange of a battle: every attacker strikes, then every defender left standing. defenders have the walls' cover and the towers' volley.
+    /// a side that loses anyone has to hold its nerve, or its fighters start to run
+    pub fn melee<R: Rng + ?Sized>(&mut self, rng: &mut R, cover: i32, volley: i32) {
+        if self.outcome.is_some() {
+            return;
+        }
+
+        self.round += 1;
+
+        let down = [self.down(Side::Attackers), self.down(Side::Defenders)];
+
+        for side in [Side::Attackers, Side::Defenders] {
+            let mut engaged = vec![0; self.combatants.len()];
+
+            let (bonus, cover) = match side {
+                Side::Attackers => (0, cover),
+                Side::Defenders => (volley, 0),
+            };
+
+            for attacker in 0..self.combatants.len() {
+                if self.combatants[attacker].side != side || !self.combatants[attacker].standing() {
+                    continue;
+                }
+
+                let Some(defender) = self.target(side.other(), &engaged) else {
+                    break;
+                };
+
+                engaged[defender] += 1;
+
+                let attacker = self.combatants[attacker].clone();
+                let roll = attacker.strike(&mut self.combatants[defender], self.round, rng, bonus, cover);
+                self.log.push(roll);
+            }
+        }
+
+        for (side, down) in [Side::Attackers, Side::Defenders].into_iter().zip(down) {
+            let now_down = self.down(side);
+
+            if now_down == down {
+                continue;
+            }
+
+            let losses = now_down as f32 / self.combatants.iter().filter(|c| c.side == side).count() as f32;
+
+            for combatant in self.combatants.iter_mut().filter(|c| c.side == side && c.standing()) {
+                if rng.gen_range(0.0..1.0) < rout_chance(combatant.loyalty, losses) {
+                    combatant.withdrawn = true;
+                }
+            }
+        }
+
+        self.settle();
+    }
+}
+
+/// carry off up to `count` items from a stockpile
+fn plunder(stockpile: &mut Inventory, count: u32) -> Vec<Stack> {
+    let mut left = count;
+    let mut taken = Vec::new();
+
+    for stack in stockpile.stacks().cloned().collect::<Vec<Stack>>() {
+        if left == 0 {
+            break;
+        }
+
+        let count = stack.count.min(left);
+
+        if stockpile.take(&stack.object, count) {
+            left -= count;
+            taken.push(Stack {
+                object: stack.object,
+                count,
+            });
+        }
+    }
+
+    taken
+}
+
+/// settle the spoils of a battle that has ended and write its report.
+/// victorious attackers plunder the stronghold, each surviving raider hitting a building on the way out, and take the plunder home.
+/// victorious defenders strip the weapons and armor from fallen raiders that aren't elves (elves' gear goes back to their own stronghold when they are buried)
+pub(crate) fn conclude<R: Rng + ?Sized>(
+    encounter: &mut Encounter,
+    colonies: &mut [Colony],
+    tick: u64,
+    rng: &mut R,
+) -> Result<Vec<GameEvent>, GameError> {
+    let (Some(outcome), Some(battle)) = (encounter.outcome, &encounter.battle) else {
+        return Ok(Vec::new());
+    };
+
+    let attackers_won = matches!(outcome, Outcome::Victory(Side::Attackers) | Outcome::Retreat(Side::Defenders));
+
+    let mut events = Vec::new();
+    let mut loot = Vec::new();
+
+    if let Some(stronghold) = stronghold_by_name_mut(colonies, &encounter.stronghold) {
+        if attackers_won {
+            for raider in encounter.combatants.iter().filter(|c| c.side == Side::Attackers && c.standing()) {
+                loot.extend(plunder(&mut stronghold.stockpile, LOOT_PER_RAIDER));
+
+                if stronghold.buildings.is_empty() {
+                    continue;
+                }
+
+                let building = stronghold.buildings[rng.gen_range(0..stronghold.buildings.len())].id;
+                events.extend(stronghold.damage_building(building, raider.damage() as u32, DamageCause::Combat, tick)?);
+            }
+        } else {
+            for fallen in encounter.combatants.iter().filter(|c| c.side == Side::Attackers && c.health <= 0 && c.elf.is_none()) {
+                for object in fallen.weapon.iter().chain(fallen.armor.iter()) {
+                    stronghold.stockpile.put(object.clone(), 1);
+                    loot.push(Stack {
+                        object: object.clone(),
+                        count: 1,
+                    });
+                }
+            }
+        }
+    }
+
+    if attackers_won {
+        if let Some(home) = battle.home.as_ref().and_then(|home| stronghold_by_name_mut(colonies, home)) {
+            home.stockpile.put_all(&loot);
+        }
+    }
+
+    let mut buildings_damaged: Vec<DamagedBuilding> = Vec::new();
+
+    for event in &events {
+        match event {
+            GameEvent::BuildingDamaged {
+                building,
+                building_type,
+                damage,
+                ..
+            } => match buildings_damaged.iter_mut().find(|b| b.building == *building) {
+                Some(damaged) => damaged.damage += damage,
+                None => buildings_damaged.push(DamagedBuilding {
+                    building: *building,
+                    building_type: *building_type,
+                    damage: *damage,
+                    destroyed: false,
+                }),
+            },
+            GameEvent::BuildingDestroyed { building, .. } => {
+                if let Some(damaged) = buildings_damaged.iter_mut().find(|b| b.building == *building) {
+                    damaged.destroyed = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let report = BattleReport {
+        outcome,
+        rounds: encounter.round,
+        casualties: encounter.combatants.iter().filter(|c| c.health <= 0).cloned().collect(),
+        fled: encounter.combatants.iter().filter(|c| c.health > 0 && c.withdrawn).cloned().collect(),
+        loot,
+        buildings_damaged,
+    };
+
+    if let Some(battle) = &mut encounter.battle {
+        battle.report = Some(report);
+    }
+
+    Ok(events)
+}
+
+pub trait Battles {
+    fn start_battle(&mut self, stronghold: &str, attackers: Vec<Combatant>, home: Option<String>) -> Result<u32, GameError>;
+    fn battle_report(&self, encounter: u32) -> Result<Option<&BattleReport>, GameError>;
+}
+
+impl Battles for World {
+    /// attack a stronghold with a force, starting a battle against everyone who turns out to defend it. it plays out over the following ticks
+    fn start_battle(&mut self, stronghold: &str, attackers: Vec<Combatant>, home: Option<String>) -> Result<u32, GameError> {
+        let target = stronghold_by_name(&self.colonies, stronghold)
+            .ok_or_else(|| GameError::NoSuitableBuildingError(format!("No stronghold {}", stronghold)))?;
+
+        if !attackers.iter().any(|c| c.side == Side::Attackers && c.standing()) {
+            return Err(GameError::NoSuitableElfError("Nobody able to attack".to_string()));
+        }
+
+        // a stronghold with nobody to defend it falls on the first exchange
+        let mut combatants = attackers;
+        combatants.extend(defenders(target));
+
+        let id = self.next_encounter_id;
+        self.next_encounter_id += 1;
+
+        let mut encounter = Encounter::new(id, stronghold.to_string(), combatants);
+        encounter.battle = Some(Battle { home, report: None });
+
+        self.encounters.push(encounter);
+
+        Ok(id)
+    }
+
+    /// the report of a battle, once it is over
+    fn battle_report(&self, encounter: u32) -> Result<Option<&BattleReport>, GameError> {
+        let battle = self
+            .encounter(encounter)?
+            .battle
+            .as_ref()
+            .ok_or_else(|| GameError::NoSuchEncounterError(format!("Encounter {} isn't a battle", encounter)))?;
+
+        Ok(battle.report.as_ref())
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{defs::*, game::*};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// ticks per second at normal speed (see World.tick)
+pub const TICKS_PER_SECOND: u32 = 20;
+
+/// most ticks run back to back when catching up, after which the backlog is dropped rather than letting the game spiral
+pub const MAX_CATCH_UP_TICKS: u32 = 100;
+
+/// how fast the game loop runs, relative to TICKS_PER_SECOND
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    Normal,
+    Double,
+    Quintuple,
+
+    /// as fast as the simulation can go
+    Max,
+}
+
+impl Speed {
+    /// tick rate multiplier, or None when running flat out
+    pub fn multiplier(&self) -> Option<u32> {
+        match self {
+            Speed::Normal => Some(1),
+            Speed::Double => Some(2),
+            Speed::Quintuple => Some(5),
+            Speed::Max => None,
+        }
+    }
+
+    /// time between ticks at this speed
+    pub fn interval(&self) -> Duration {
+        match self.multiplier() {
+            Some(multiplier) => Duration::from_secs(1) / (TICKS_PER_SECOND * multiplier),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// commands sent from the UI to the simulation thread
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopCommand {
+    Pause,
+    Resume,
+
+    /// pause (if running) and advance exactly one tick
+    Step,
+    SetSpeed(Speed),
+    Stop,
+}
+
+/// state of the loop as last seen by the simulation thread
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopStatus {
+    pub paused: bool,
+    pub speed: Speed,
+
+    /// ticks dropped because the loop fell more than MAX_CATCH_UP_TICKS behind
+    pub dropped_ticks: u64,
+}
+
+/// Drives World::tick on its own thread at a fixed rate. the world sits behind a mutex, so a UI can read or command it between ticks without racing the simulation
+pub struct GameLoop {
+    world: Arc<Mutex<World>>,
+    status: Arc<Mutex<LoopStatus>>,
+    commands: Sender<LoopCommand>,
+    thread: Option<JoinHandle<Result<(), GameError>>>,
+}
+
+impl GameLoop {
+    /// start ticking the world at normal speed
+    pub fn start(world: World) -> Self {
+        let world = Arc::new(Mutex::new(world));
+        let status = Arc::new(Mutex::new(LoopStatus {
+            paused: false,
+            speed: Speed::Normal,
+            dropped_ticks: 0,
+        }));
+        let (commands, receiver) = mpsc::channel();
+
+        let thread = {
+            let world = Arc::clone(&world);
+            let status = Arc::clone(&status);
+            thread::spawn(move || run(world, status, receiver))
+        };
+
+        GameLoop {
+            world,
+            status,
+            commands,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn pause(&self) {
+        self.send(LoopCommand::Pause);
+    }
+
+    pub fn resume(&self) {
+        self.send(LoopCommand::Resume);
+    }
+
+    pub fn step(&self) {
+        self.send(LoopCommand::Step);
+    }
+
+    pub fn set_speed(&self, speed: Speed) {
+        self.send(LoopCommand::SetSpeed(speed));
+    }
+
+    /// commands are dropped once the simulation thread has stopped, which stop() reports
+    fn send(&self, command: LoopCommand) {
+        let _ = self.commands.send(command);
+    }
+
+    pub fn status(&self) -> LoopStatus {
+        *lock(&self.status)
+    }
+
+    /// run a closure against the world between two ticks
+    pub fn with_world<T>(&self, f: impl FnOnce(&World) -> T) -> T {
+        f(&lock(&self.world))
+    }
+
+    /// run a closure that changes the world between two ticks (ex. queueing tasks)
+    pub fn with_world_mut<T>(&self, f: impl FnOnce(&mut World) -> T) -> T {
+        f(&mut lock(&self.world))
+    }
+
+    /// stop the simulation thread and hand back the world, or the error that stopped it
+    pub fn stop(mut self) -> Result<World, GameError> {
+        self.send(LoopCommand::Stop);
+
+        if let Some(thread) = self.thread.take() {
+            thread
+                .join()
+                .map_err(|_| GameError::LoopError("Simulation thread panicked".to_string()))??;
+        }
+
+        let world = Arc::clone(&self.world);
+        drop(self);
+
+        match Arc::try_unwrap(world) {
+            Ok(world) => Ok(world.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())),
+            Err(_) => Err(GameError::LoopError("World is still borrowed".to_string())),
+        }
+    }
+}
+
+impl Drop for GameLoop {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.send(LoopCommand::Stop);
+            let _ = thread.join();
+        }
+    }
+}
+
+/// a panicking UI closure shouldn't take the simulation down with it
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// body of the simulation thread
+fn run(
+    world: Arc<Mutex<World>>,
+    status: Arc<Mutex<LoopStatus>>,
+    commands: Receiver<LoopCommand>,
+) -> Result<(), GameError> {
+    let mut next_tick = Instant::now();
+
+    loop {
+        let current = *lock(&status);
+
+        let command = if current.paused {
+            // nothing to do until told otherwise
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return Ok(()),
+            }
+        } else {
+            // run every tick that is due, then wait for the next one (or a command)
+            let now = Instant::now();
+            let interval = current.speed.interval();
+            let mut ran = 0;
+
+            while next_tick <= now && ran < MAX_CATCH_UP_TICKS {
+                lock(&world).tick()?;
+                next_tick += interval;
+                ran += 1;
+            }
+
+            if ran == MAX_CATCH_UP_TICKS && next_tick <= now {
+                // at max speed there is no schedule to fall behind
+                if !interval.is_zero() {
+                    let behind = (now - next_tick).as_nanos() / interval.as_nanos();
+                    lock(&status).dropped_ticks += behind as u64;
+                }
+                next_tick = now;
+            }
+
+            match commands.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        };
+
+        // never hold the status lock while ticking, or a UI holding the world could deadlock us
+        match command {
+            Some(LoopCommand::Pause) => lock(&status).paused = true,
+            Some(LoopCommand::Resume) => {
+                lock(&status).paused = false;
+                next_tick = Instant::now();
+            }
+            Some(LoopCommand::Step) => {
+                lock(&status).paused = true;
+                lock(&world).tick()?;
+            }
+            Some(LoopCommand::SetSpeed(speed)) => {
+                lock(&status).speed = speed;
+                next_tick = Instant::now();
+            }
+            Some(LoopCommand::Stop) => return Ok(()),
+            None => {}
+        }
+    }
+}
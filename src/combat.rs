@@ -0,0 +1,679 @@
+use crate::{battle::*, defs::*, game::*, impls::*};
+use rand::Rng;
+
+/// finished encounters kept for the UI to look back on
+pub const ENCOUNTER_HISTORY: usize = 20;
+
+/// damage power of bare hands
+pub const UNARMED_DAMAGE: u32 = 1;
+
+/// least damage a hit that lands does, however good the armor, so every fight ends
+pub const MIN_DAMAGE: i32 = 1;
+
+/// chance of dodging an attack (agile, weak fighters dodge more)
+pub fn dodge_chance(agility: AttributeLevel, strength: AttributeLevel) -> f32 {
+    0.5 + (agility as i32 - strength as i32) as f32 / 100.0
+}
+
+/// chance of an attack landing a critical hit, for double damage (strong, clumsy fighters crit more)
+pub fn crit_chance(strength: AttributeLevel, agility: AttributeLevel) -> f32 {
+    0.5 + (strength as i32 - agility as i32) as f32 / 100.0
+}
+
+impl ObjectType {
+    /// agility needed to use a piece of equipment, and how much agility it takes off its user (None if it isn't equipment)
+    pub fn agility_rules(&self) -> Option<(AttributeLevel, AttributeLevel)> {
+        match self {
+            ObjectType::Weapon { agility_requirement, agility_penalty, .. }
+            | ObjectType::Armor { agility_requirement, agility_penalty, .. }
+            | ObjectType::PickAxe { agility_requirement, agility_penalty, .. } => Some((*agility_requirement, *agility_penalty)),
+            _ => None,
+        }
+    }
+}
+
+/// check someone is agile enough to use a piece of equipment
+pub fn check_agility(agility: AttributeLevel, object: &Object) -> Result<(), GameError> {
+    let (requirement, _) = object
+        .object_type
+        .agility_rules()
+        .ok_or_else(|| GameError::EquipmentError(format!("{} isn't equipment", object.object_type.name())))?;
+
+    if (agility as i32) < requirement as i32 {
+        return Err(GameError::EquipmentError(format!(
+            "{} needs {:?} agility",
+            object.object_type.name(),
+            requirement
+        )));
+    }
+
+    Ok(())
+}
+
+/// check someone is agile enough to use everything they would have on, once every piece has taken its penalty off
+pub fn check_equipment(agility: AttributeLevel, equipment: &[&Object]) -> Result<(), GameError> {
+    let agility = effective_agility(agility, equipment.iter().copied());
+
+    equipment.iter().try_for_each(|object| check_agility(agility, object))
+}
+
+/// agility left after each piece of equipment takes its penalty off
+pub fn effective_agility<'a>(agility: AttributeLevel, equipment: impl IntoIterator<Item = &'a Object>) -> AttributeLevel {
+    equipment
+        .into_iter()
+        .filter_map(|object| object.object_type.agility_rules())
+        .fold(agility, |agility, (_, penalty)| agility.lower(penalty as u32))
+}
+
+impl Combatant {
+    /// an elf of a stronghold, joining a fight with its equipment
+    pub fn elf(stronghold: &Stronghold, elf: &Elf, side: Side) -> Self {
+        // the weapon and armor take their agility penalties in the fight, but the tool is carried into it
+        Combatant {
+            agility: effective_agility(elf.agility, elf.equipment.tool.iter()),
+            weapon: elf.equipment.main_hand.clone(),
+            armor: elf.equipment.body_armor.clone(),
+            elf: Some((stronghold.name.clone(), elf.id)),
+            ..Combatant::entity(&Entity::Elf(elf.clone()), side)
+        }
+    }
+
+    pub fn goblin(goblin: &Goblin, side: Side) -> Self {
+        Combatant {
+            name: goblin.name.join(" "),
+            side,
+            strength: goblin.strength,
+            agility: goblin.agility,
+            health: goblin.health,
+            loyalty: goblin.loyalty,
+            weapon: None,
+            armor: None,
+            elf: None,
+            withdrawn: false,
+        }
+    }
+
+    /// anyone, fighting bare handed and unarmored
+    pub fn entity(entity: &Entity, side: Side) -> Self {
+        Combatant {
+            name: entity.name(),
+            side,
+            strength: entity.strength(),
+            agility: entity.agility(),
+            health: entity.health(),
+            loyalty: match entity {
+                Entity::Elf(elf) => elf.loyalty,
+                Entity::Goblin(goblin) => goblin.loyalty,
+            },
+            weapon: None,
+            armor: None,
+            elf: None,
+            withdrawn: false,
+        }
+    }
+
+    /// take up a weapon or put on armor, returning whatever it replaces. as with an elf, everything kept on has to be usable with the new piece on too
+    pub fn equip(&mut self, object: Object) -> Result<Option<Object>, GameError> {
+        let (slot, other) = match object.object_type {
+            ObjectType::Weapon { .. } => (&mut self.weapon, &self.armor),
+            ObjectType::Armor { .. } => (&mut self.armor, &self.weapon),
+            _ => return Err(GameError::EquipmentError(format!("{} can't be fought with", object.object_type.name()))),
+        };
+
+        check_equipment(self.agility, &other.iter().chain([&object]).collect::<Vec<&Object>>())?;
+
+        Ok(slot.replace(object))
+    }
+
+    /// agility after the weapon and armor take their penalties off
+    pub fn effective_agility(&self) -> AttributeLevel {
+        effective_agility(self.agility, self.weapon.iter().chain(self.armor.iter()))
+    }
+
+    /// damage of a hit before armor: weapon damage power scaled by strength
+    pub fn damage(&self) -> i32 {
+        let power = match &self.weapon {
+            Some(Object {
+                object_type: ObjectType::Weapon { damage_power, .. },
+                ..
+            }) => *damage_power,
+            _ => UNARMED_DAMAGE,
+        };
+
+        (power * self.strength as u32) as i32
+    }
+
+    /// damage the armor takes off each hit
+    pub fn defense(&self) -> i32 {
+        match &self.armor {
+            Some(Object {
+                object_type: ObjectType::Armor { defense_power, .. },
+                ..
+            }) => *defense_power as i32,
+            _ => 0,
+        }
+    }
+
+    /// still in the fight
+    pub fn standing(&self) -> bool {
+        self.health > 0 && !self.withdrawn
+    }
+
+    /// attack another combatant once
+    pub fn attack<R: Rng + ?Sized>(&self, defender: &mut Combatant, round: u32, rng: &mut R) -> CombatRoll {
+        self.strike(defender, round, rng, 0, 0)
+    }
+
+    /// attack another combatant once, with extra damage on the hit and extra cover for the defender (from towers and walls)
+    pub fn strike<R: Rng + ?Sized>(
+        &self,
+        defender: &mut Combatant,
+        round: u32,
+        rng: &mut R,
+        bonus: i32,
+        cover: i32,
+    ) -> CombatRoll {
+        let dodge_roll = rng.gen_range(0.0..1.0);
+        let crit_roll = rng.gen_range(0.0..1.0);
+
+        let dodged = dodge_roll < dodge_chance(defender.effective_agility(), defender.strength);
+        let crit = !dodged && crit_roll < crit_chance(self.strength, self.effective_agility());
+
+        let hit = match (dodged, crit) {
+            (true, _) => 0,
+            (false, true) => (self.damage() + bonus) * 2,
+            (false, false) => self.damage() + bonus,
+        };
+
+        // armor soaks up part of a hit that lands, but never all of it
+        let damage = if dodged { 0 } else { (hit - defender.defense() - cover).max(MIN_DAMAGE) };
+
+        defender.health -= damage;
+
+        CombatRoll {
+            round,
+            attacker: self.name.clone(),
+            defender: defender.name.clone(),
+            dodge_roll,
+            dodged,
+            crit_roll,
+            crit,
+            damage,
+            blocked: hit - damage,
+            health: defender.health,
+        }
+    }
+}
+
+impl Side {
+    pub fn other(&self) -> Side {
+        match self {
+            Side::Attackers => Side::Defenders,
+            Side::Defenders => Side::Attackers,
+        }
+    }
+}
+
+impl Encounter {
+    pub fn new(id: u32, stronghold: String, combatants: Vec<Combatant>) -> Self {
+        Encounter {
+            id,
+            stronghold,
+            combatants,
+            round: 0,
+            log: Vec::new(),
+            outcome: None,
+            battle: None,
+        }
+    }
+
+    /// position of the first combatant of a side still standing - the one doing the fighting
+    pub fn front(&self, side: Side) -> Option<usize> {
+        self.combatants.iter().position(|c| c.side == side && c.standing())
+    }
+
+    /// fight one exchange: each side's front combatant attacks the other's, attackers first. a fighter downed by the first attack doesn't strike back
+    pub fn exchange<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        if self.outcome.is_some() {
+            return;
+        }
+
+        self.round += 1;
+
+        for side in [Side::Attackers, Side::Defenders] {
+            let (Some(attacker), Some(defender)) = (self.front(side), self.front(side.other())) else {
+                break;
+            };
+
+            let attacker = self.combatants[attacker].clone();
+            let roll = attacker.attack(&mut self.combatants[defender], self.round, rng);
+            self.log.push(roll);
+        }
+
+        self.settle();
+    }
+
+    /// end the fight once a side has nobody standing
+    pub fn settle(&mut self) {
+        if self.outcome.is_some() {
+            return;
+        }
+
+        for side in [Side::Attackers, Side::Defenders] {
+            if self.front(side).is_none() {
+                self.outcome = Some(Outcome::Victory(side.other()));
+                return;
+            }
+        }
+    }
+}
+
+pub trait Combat {
+    fn start_encounter(&mut self, stronghold: &str, combatants: Vec<Combatant>) -> Result<u32, GameError>;
+    fn encounter(&self, encounter: u32) -> Result<&Encounter, GameError>;
+    fn reinforce(&mut self, encounter: u32, combatant: Combatant) -> Result<(), GameError>;
+    fn retreat(&mut self, encounter: u32, side: Side) -> Result<(), GameError>;
+    fn tick_encounters(&mut self) -> Result<Vec<GameEvent>, GameError>;
+}
+
+fn encounter_mut(world: &mut World, encounter: u32) -> Result<&mut Encounter, GameError> {
+    world
+        .encounters
+        .iter_mut()
+        .find(|e| e.id == encounter)
+        .ok_or_else(|| GameError::NoSuchEncounterError(format!("No encounter {}", encounter)))
+}
+
+/// an ongoing encounter, or an error if it has finished
+fn ongoing_mut(world: &mut World, encounter: u32) -> Result<&mut Encounter, GameError> {
+    let found = encounter_mut(world, encounter)?;
+
+    if found.outcome.is_some() {
+        return Err(GameError::NoSuchEncounterError(format!("Encounter {} is over", encounter)));
+    }
+
+    Ok(found)
+}
+
+impl Combat for World {
+    /// start a fight at a stronghold. it plays out over the following ticks
+    fn start_encounter(&mut self, stronghold: &str, combatants: Vec<Combatant>) -> Result<u32, GameError> {
+        for side in [Side::Attackers, Side::Defenders] {
+            if !combatants.iter().any(|c| c.side == side && c.standing()) {
+                return Err(GameError::NoSuitableElfError(format!("Nobody able to fight on the {:?} side", side)));
+            }
+        }
+
+        let id = self.next_encounter_id;
+        self.next_encounter_id += 1;
+
+        self.encounters.push(Encounter::new(id, stronghold.to_string(), combatants));
+
+        Ok(id)
+    }
+
+    fn encounter(&self, encounter: u32) -> Result<&Encounter, GameError> {
+        self.encounters
+            .iter()
+            .find(|e| e.id == encounter)
+            .ok_or_else(|| GameError::NoSuchEncounterError(format!("No encounter {}", encounter)))
+    }
+
+    /// join a fight underway. reinforcements wait behind whoever is already fighting on their side
+    fn reinforce(&mut self, encounter: u32, combatant: Combatant) -> Result<(), GameError> {
+        ongoing_mut(self, encounter)?.combatants.push(combatant);
+
+        Ok(())
+    }
+
+    /// pull a side out of a fight underway, ending it. a battle is reported on the next tick, but a duel is over there and then
+    fn retreat(&mut self, encounter: u32, side: Side) -> Result<(), GameError> {
+        let tick = self.tick;
+        let found = ongoing_mut(self, encounter)?;
+
+        for combatant in found.combatants.iter_mut().filter(|c| c.side == side) {
+            combatant.withdrawn = true;
+        }
+
+        found.outcome = Some(Outcome::Retreat(side));
+
+        if found.battle.is_none() {
+            let event = GameEvent::EncounterEnded {
+                tick,
+                encounter,
+                stronghold: found.stronghold.clone(),
+                outcome: Outcome::Retreat(side),
+            };
+
+            self.events.push(event);
+        }
+
+        Ok(())
+    }
+
+    /// fight an exchange in every encounter underway, carrying elves' wounds back to their strongholds. battles that have ended are reported on
+    fn tick_encounters(&mut self) -> Result<Vec<GameEvent>, GameError> {
+        let mut events = Vec::new();
+
+        for encounter in &mut self.encounters {
+            let reported = encounter.battle.as_ref().is_none_or(|battle| battle.report.is_some());
+
+            if encounter.outcome.is_some() && reported {
+                continue;
+            }
+
+            if encounter.outcome.is_none() {
+                // elves that died or left their stronghold since the last exchange are out of the fight
+                refresh_elves(&self.colonies, &mut encounter.combatants);
+
+                encounter.settle();
+
+                if encounter.battle.is_some() {
+                    let (cover, volley) = stronghold_by_name(&self.colonies, &encounter.stronghold)
+                        .map(fortified)
+                        .unwrap_or((0, 0));
+
+                    encounter.melee(&mut self.rng, cover, volley);
+                } else {
+                    encounter.exchange(&mut self.rng);
+                }
+
+                wound_elves(&mut self.colonies, &encounter.combatants);
+            }
+
+            if let Some(outcome) = encounter.outcome {
+                if encounter.battle.is_some() {
+                    events.extend(conclude(encounter, &mut self.colonies, self.tick, &mut self.rng)?);
+                }
+
+                events.push(GameEvent::EncounterEnded {
+                    tick: self.tick,
+                    encounter: encounter.id,
+                    stronghold: encounter.stronghold.clone(),
+                    outcome,
+                });
+            }
+        }
+
+        // keep only the latest finished encounters
+        let finished = self.encounters.iter().filter(|e| e.outcome.is_some()).count();
+
+        if finished > ENCOUNTER_HISTORY {
+            let mut excess = finished - ENCOUNTER_HISTORY;
+
+            self.encounters.retain(|e| {
+                if e.outcome.is_some() && excess > 0 {
+                    excess -= 1;
+                    return false;
+                }
+
+                true
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+pub(crate) fn stronghold_by_name<'a>(colonies: &'a [Colony], name: &str) -> Option<&'a Stronghold> {
+    colonies
+        .iter()
+        .flat_map(|colony| colony.strongholds.iter())
+        .find(|stronghold| stronghold.name == name)
+}
+
+pub(crate) fn stronghold_by_name_mut<'a>(colonies: &'a mut [Colony], name: &str) -> Option<&'a mut Stronghold> {
+    colonies
+        .iter_mut()
+        .flat_map(|colony| colony.strongholds.iter_mut())
+        .find(|stronghold| stronghold.name == name)
+}
+
+/// bring combatants' health up to date with their elves, taking out those whose elf is gone
+fn refresh_elves(colonies: &[Colony], combatants: &mut [Combatant]) {
+    for combatant in combatants {
+        if let Some((stronghold, elf)) = &combatant.elf {
+            let found = stronghold_by_name(colonies, stronghold).and_then(|s| s.elves.iter().find(|e| e.id == *elf));
+
+            match found {
+                Some(elf) => combatant.health = elf.health,
+                None => combatant.withdrawn = true,
+            }
+        }
+    }
+}
+
+/// carry combatants' wounds back to their elves
+fn wound_elves(colonies: &mut [Colony], combatants: &[Combatant]) {
+    for combatant in combatants {
+        if let Some((stronghold, elf)) = &combatant.elf {
+            let found = stronghold_by_name_mut(colonies, stronghold).and_then(|s| s.elves.iter_mut().find(|e| e.id == *elf));
+
+            if let Some(elf) = found {
+                elf.health = combatant.health;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ended(world: &World, encounter: u32) -> usize {
+        world
+            .events
+            .iter()
+            .filter(|event| matches!(event, GameEvent::EncounterEnded { encounter: e, .. } if *e == encounter))
+            .count()
+    }
+
+    #[test]
+    fn retreating_from_a_duel_ends_it_once() {
+        let mut world = World::new(None, None, Some(5));
+        let stronghold = world.colonies[0].strongholds[0].name.clone();
+        let goblin = world.goblin_strongholds[0].goblins[0].clone();
+
+        let duel = world
+            .start_encounter(
+                &stronghold,
+                vec![Combatant::goblin(&goblin, Side::Attackers), Combatant::goblin(&goblin, Side::Defenders)],
+            )
+            .unwrap();
+
+        world.retreat(duel, Side::Attackers).unwrap();
+        assert_eq!(ended(&world, duel), 1);
+
+        for _ in 0..3 {
+            world.tick().unwrap();
+        }
+
+        assert_eq!(ended(&world, duel), 1);
+        assert_eq!(world.encounter(duel).unwrap().outcome, Some(Outcome::Retreat(Side::Attackers)));
+    }
+
+    use crate::rng::*;
+
+    const LEVELS: [AttributeLevel; 5] = [
+        AttributeLevel::Terrible,
+        AttributeLevel::Poor,
+        AttributeLevel::Average,
+        AttributeLevel::Good,
+        AttributeLevel::Excellent,
+    ];
+
+    /// exchanges any fight must be over within, even between the weakest fighters in the heaviest armor
+    const MAX_EXCHANGES: u32 = 2000;
+
+    fn weapons() -> Vec<Option<Object>> {
+        [1, 10]
+            .into_iter()
+            .map(|damage_power| {
+                Some(Object {
+                    resource_type: ResourceType::Iron,
+                    object_type: ObjectType::Weapon {
+                        name: format!("Sword {}", damage_power),
+                        description: "Test weapon".to_string(),
+                        damage_power,
+                        agility_requirement: AttributeLevel::Terrible,
+                        agility_penalty: AttributeLevel::Terrible,
+                    },
+                })
+            })
+            .chain([None])
+            .collect()
+    }
+
+    /// from light armor to armor no hit gets through
+    fn armors() -> Vec<Option<Object>> {
+        [1, 1000]
+            .into_iter()
+            .map(|defense_power| {
+                Some(Object {
+                    resource_type: ResourceType::Iron,
+                    object_type: ObjectType::Armor {
+                        name: format!("Mail {}", defense_power),
+                        description: "Test armor".to_string(),
+                        defense_power,
+                        agility_requirement: AttributeLevel::Terrible,
+                        agility_penalty: AttributeLevel::Poor,
+                    },
+                })
+            })
+            .chain([None])
+            .collect()
+    }
+
+    /// every combination of strength, agility, weapon and armor
+    fn fighters(side: Side) -> Vec<Combatant> {
+        let mut fighters = Vec::new();
+
+        for strength in LEVELS {
+            for agility in LEVELS {
+                for weapon in weapons() {
+                    for armor in armors() {
+                        fighters.push(Combatant {
+                            name: format!("{:?} {:?}", strength, agility),
+                            side,
+                            strength,
+                            agility,
+                            health: 100,
+                            loyalty: AttributeLevel::Average,
+                            weapon: weapon.clone(),
+                            armor,
+                            elf: None,
+                            withdrawn: false,
+                        });
+                    }
+                }
+            }
+        }
+
+        fighters
+    }
+
+    /// every hit in the log took health, never gave it, and agrees with what is left
+    fn check_log(encounter: &Encounter, before: &[Combatant]) {
+        for roll in &encounter.log {
+            assert!(roll.dodged || roll.damage >= MIN_DAMAGE, "{:?}", roll);
+            assert!(!roll.dodged || roll.damage == 0, "{:?}", roll);
+        }
+
+        for (combatant, start) in encounter.combatants.iter().zip(before) {
+            let taken = encounter
+                .log
+                .iter()
+                .filter(|roll| roll.defender == combatant.name)
+                .map(|roll| roll.damage)
+                .sum::<i32>();
+
+            assert!(combatant.health <= start.health);
+            assert_eq!(start.health - combatant.health, taken);
+        }
+    }
+
+    #[test]
+    fn every_duel_ends_and_hurts() {
+        let defenders = fighters(Side::Defenders);
+
+        for (n, mut attacker) in fighters(Side::Attackers).into_iter().enumerate() {
+            attacker.name = format!("Attacker {}", attacker.name);
+
+            for seed in 0..4 {
+                let defender = defenders[(n * 7 + seed as usize) % defenders.len()].clone();
+                let combatants = vec![attacker.clone(), defender];
+
+                let mut rng = GameRng::new(seed);
+                let mut encounter = Encounter::new(0, String::new(), combatants.clone());
+
+                while encounter.outcome.is_none() {
+                    assert!(encounter.round < MAX_EXCHANGES, "{:?} never ended", combatants);
+                    encounter.exchange(&mut rng);
+                }
+
+                check_log(&encounter, &combatants);
+
+                let Some(Outcome::Victory(side)) = encounter.outcome else {
+                    panic!("a duel can only be won, got {:?}", encounter.outcome);
+                };
+
+                let loser = encounter.combatants.iter().find(|c| c.side == side.other()).unwrap();
+                assert!(loser.health <= 0);
+            }
+        }
+    }
+
+    #[test]
+    fn every_melee_ends_and_hurts() {
+        let fighters = fighters(Side::Attackers);
+
+        for seed in 0..40 {
+            let mut rng = GameRng::new(seed);
+            let pick = |n: u64, side: Side| {
+                let mut fighter = fighters[(seed * 31 + n * 17) as usize % fighters.len()].clone();
+                fighter.name = format!("{:?} {} {}", side, n, fighter.name);
+                fighter.side = side;
+                fighter
+            };
+
+            let mut combatants = (0..1 + seed % 4).map(|n| pick(n, Side::Attackers)).collect::<Vec<Combatant>>();
+            combatants.extend((0..1 + seed % 3).map(|n| pick(n + 10, Side::Defenders)));
+
+            for (cover, volley) in [(0, 0), (5, 5), (1000, 0), (0, 1000)] {
+                let mut encounter = Encounter::new(0, String::new(), combatants.clone());
+
+                while encounter.outcome.is_none() {
+                    assert!(encounter.round < MAX_EXCHANGES, "{:?} never ended", combatants);
+                    encounter.melee(&mut rng, cover, volley);
+                }
+
+                check_log(&encounter, &combatants);
+            }
+        }
+    }
+
+    #[test]
+    fn fights_change_the_fighters_health() {
+        for seed in 0..50 {
+            let mut rng = GameRng::new(seed);
+
+            for strength in LEVELS {
+                for agility in LEVELS {
+                    let mut goblin = Goblin::new(&mut rng, None, None, None);
+                    goblin.strength = strength;
+                    goblin.agility = agility;
+                    goblin.health = 100;
+
+                    let mut other = Goblin::new(&mut rng, None, None, None);
+                    other.health = 100;
+
+                    let mut entity = Entity::Goblin(goblin);
+                    let won = entity.fight(Entity::Goblin(other), &mut rng).unwrap();
+
+                    assert!(entity.health() <= 100);
+                    assert_eq!(won, entity.health() > 0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{defs::*, durability::*, game::*, impls::*, needs::*, skills::*, stats::*};
+
+/// base ticks of work per level being built (an in game hour a level)
+pub const BUILD_TICKS_PER_LEVEL: u32 = TICKS_PER_HOUR as u32;
+
+/// what each level of a building type costs, by resource. a level costs this many times its number
+pub fn building_materials(building_type: BuildingType) -> Vec<ResourceCost> {
+    use ResourceType::*;
+
+    let materials: &[(ResourceType, u32)] = match building_type {
+        BuildingType::MeetingHall => &[(Wood, 4), (Stone, 2)],
+        BuildingType::Barracks => &[(Wood, 3), (Stone, 2), (Iron, 1)],
+        BuildingType::Farm => &[(Wood, 3), (Plant, 2)],
+        BuildingType::HuntingLodge => &[(Wood, 3), (Animal, 1)],
+        BuildingType::GatheringHut => &[(Wood, 3)],
+        BuildingType::CarpenterWorkshop => &[(Wood, 4), (Iron, 1)],
+        BuildingType::StonemasonWorkshop => &[(Stone, 4), (Wood, 1)],
+        BuildingType::Forge => &[(Stone, 3), (Iron, 2)],
+        BuildingType::TailorShop => &[(Wood, 2), (Cloth, 2)],
+        BuildingType::Kitchen => &[(Stone, 2), (Wood, 2)],
+        BuildingType::Hospital => &[(Wood, 3), (Cloth, 2)],
+        BuildingType::HerbalistHut => &[(Wood, 2), (Plant, 2)],
+        BuildingType::AlchemistLab => &[(Stone, 2), (Glass, 2), (Magical, 1)],
+        BuildingType::Mine => &[(Wood, 2), (Stone, 2), (Iron, 1)],
+        BuildingType::BuilderHut => &[(Wood, 3), (Stone, 1)],
+        BuildingType::Laboratory => &[(Stone, 2), (Glass, 2), (Technological, 1)],
+        BuildingType::TradingPost => &[(Wood, 3), (Gold, 1)],
+        BuildingType::Tower => &[(Stone, 4), (Wood, 1)],
+        BuildingType::Wall => &[(Stone, 5)],
+        BuildingType::Dormitory => &[(Wood, 4), (Cloth, 1)],
+    };
+
+    materials
+        .iter()
+        .map(|(resource, amount)| ResourceCost {
+            resource: *resource,
+            amount: *amount,
+        })
+        .collect()
+}
+
+/// cost of bringing a building up to a level (level 1 being a new building)
+pub fn building_cost(building_type: BuildingType, level: u32) -> Cost {
+    building_materials(building_type)
+        .into_iter()
+        .fold(Cost::new(), |cost, material| cost.and_resource(material.resource, material.amount * level))
+}
+
+/// base duration of the work to bring a building up to a level
+pub fn build_duration(level: u32) -> u32 {
+    BUILD_TICKS_PER_LEVEL * level
+}
+
+pub trait Construction {
+    fn builder_role(&self, building_type: BuildingType) -> Role;
+    fn build_site(&self) -> BuildingType;
+    fn build(&mut self, building_type: BuildingType) -> Result<u32, GameError>;
+    fn upgrade(&mut self, building: u32) -> Result<u32, GameError>;
+    fn repurpose(&mut self, building: u32, building_type: BuildingType) -> Result<u32, GameError>;
+    fn demolish(&mut self, building: u32) -> Result<Building, GameError>;
+    fn start_projects(&mut self, projects: Vec<(BuildingType, Option<u32>)>) -> Result<Vec<u32>, GameError>;
+    fn project(&self, task_id: u32) -> Option<&Project>;
+    fn project_progress(&self, task_id: u32) -> Option<(u32, u32)>;
+    fn finish_project(&mut self, task_id: u32) -> Option<u32>;
+    fn abandon_project(&mut self, task_id: u32) -> Option<Project>;
+}
+
+impl Construction for Stronghold {
+    /// builders put up anything, but with none about carpenters and stonemasons can put up buildings mostly of their material
+    fn builder_role(&self, building_type: BuildingType) -> Role {
+        let has_role = |role: &Role| self.elves.iter().any(|elf| role_ability(elf, role).is_some());
+
+        if has_role(&Role::Builder) {
+            return Role::Builder;
+        }
+
+        let craft = match building_materials(building_type).first().map(|material| material.resource) {
+            Some(ResourceType::Wood) => Role::Carpenter,
+            Some(ResourceType::Stone) => Role::Stonemason,
+            _ => Role::Builder,
+        };
+
+        if has_role(&craft) {
+            craft
+        } else {
+            Role::Builder
+        }
+    }
+
+    /// building work is organised from the builder hut, or the meeting hall until there is one
+    fn build_site(&self) -> BuildingType {
+        if self
+            .buildings
+            .iter()
+            .any(|building| building.building_type == BuildingType::BuilderHut)
+        {
+            BuildingType::BuilderHut
+        } else {
+            BuildingType::MeetingHall
+        }
+    }
+
+    /// start putting up a new building, returning its build task ID
+    fn build(&mut self, building_type: BuildingType) -> Result<u32, GameError> {
+        Ok(self.start_projects(vec![(building_type, None)])?[0])
+    }
+
+    /// start upgrading one building by a level, returning its build task ID
+    fn upgrade(&mut self, building: u32) -> Result<u32, GameError> {
+        let building_type = building_by_id(self, building)?.building_type;
+
+        Ok(self.start_projects(vec![(building_type, Some(building))])?[0])
+    }
+
+    /// start turning one building into another type, at the same level. tasks of the old type carry on there until it is done
+    fn repurpose(&mut self, building: u32, building_type: BuildingType) -> Result<u32, GameError> {
+        if building_by_id(self, building)?.building_type == building_type {
+            return Err(GameError::NoSuitableBuildingError(format!("Building {} is already a {:?}", building, building_type)));
+        }
+
+        Ok(self.start_projects(vec![(building_type, Some(building))])?[0])
+    }
+
+    /// knock a building down. tasks there go back to the front of the queue, and any work on it is cancelled and paid back
+    fn demolish(&mut self, building: u32) -> Result<Building, GameError> {
+        let position = self
+            .buildings
+            .iter()
+            .position(|b| b.id == building)
+            .ok_or_else(|| GameError::NoSuitableBuildingError(format!("No building {}", building)))?;
+
+        if let Some(project) = self.projects.iter().find(|project| project.building == Some(building)) {
+            self.cancel_task(project.task)?;
+        }
+
+        evict(self, building);
+
+        Ok(self.buildings.remove(position))
+    }
+
+    /// pay for and queue the work on buildings, returning the build task IDs. either every project starts or none do
+    /// (type, None) - put up a new building of the type
+    /// (type, Some(ID)) - upgrade the building if it is already that type, otherwise repurpose it as that type
+    fn start_projects(&mut self, projects: Vec<(BuildingType, Option<u32>)>) -> Result<Vec<u32>, GameError> {
+        let mut planned: Vec<(Task, Project, Cost)> = Vec::new();
+
+        for (building_type, building) in projects {
+            let level = match building {
+                Some(id) => {
+                    if self.projects.iter().any(|project| project.building == Some(id))
+                        || planned.iter().any(|(_, project, _)| project.building == Some(id))
+                    {
+                        return Err(GameError::ProjectExistsError(format!("Building {} is already being worked on", id)));
+                    }
+
+                    let existing = building_by_id(self, id)?;
+
+                    if existing.building_type == building_type {
+                        existing.level + 1
+                    } else {
+                        existing.level
+                    }
+                }
+                None => 1,
+            };
+
+            let task = Task::new(
+                format!("Build {:?} (level {})", building_type, level),
+                build_duration(level),
+                self.build_site(),
+                vec![RoleRequirement {
+                    role: self.builder_role(building_type),
+                    workers: 1,
+                }],
+            );
+
+            let project = Project {
+                task: 0,
+                building_type,
+                building,
+                level,
+                paid: Vec::new(),
+            };
+
+            planned.push((task, project, building_cost(building_type, level)));
+        }
+
+        // buildings still going up count towards the stronghold's size
+        let new_buildings = self.buildings.len()
+            + self.projects.iter().filter(|project| project.building.is_none()).count()
+            + planned.iter().filter(|(_, project, _)| project.building.is_none()).count();
+
+        if new_buildings as u32 > self.building_capacity() {
+            return Err(GameError::CapacityError(format!("{} has no room for more buildings", self.name)));
+        }
+
+        launch(self, planned)
+    }
+
+    fn project(&self, task_id: u32) -> Option<&Project> {
+        self.projects.iter().find(|project| project.task == task_id)
+    }
+
+    /// ticks worked on a project so far, and ticks of work it needs altogether
+    fn project_progress(&self, task_id: u32) -> Option<(u32, u32)> {
+        self.project(task_id)?;
+        let task = self.task(task_id)?;
+
+        Some((task.progress, task_duration(task, &self.elves)))
+    }
+
+    /// raise, repurpose or repair the building once its build task is done (leaving it at full health), returning its ID
+    fn finish_project(&mut self, task_id: u32) -> Option<u32> {
+        let position = self.projects.iter().position(|project| project.task == task_id)?;
+        let project = self.projects.remove(position);
+
+        match project.building {
+            Some(id) => {
+                let building = self.buildings.iter_mut().find(|b| b.id == id)?;
+                let repurposed = building.building_type != project.building_type;
+
+                building.building_type = project.building_type;
+                building.level = project.level;
+                building.health = max_health(project.level);
+
+                // tasks of the old type can't carry on in a repurposed building
+                if repurposed {
+                    evict(self, id);
+                }
+
+                Some(id)
+            }
+            None => {
+                let id = self.next_building_id;
+                self.next_building_id += 1;
+
+                self.buildings.push(Building {
+                    id,
+                    building_type: project.building_type,
+                    level: project.level,
+                    health: max_health(project.level),
+                });
+
+                Some(id)
+            }
+        }
+    }
+
+    /// drop a project whose build task was cancelled, giving back what was paid for it
+    fn abandon_project(&mut self, task_id: u32) -> Option<Project> {
+        let position = self.projects.iter().position(|project| project.task == task_id)?;
+        let project = self.projects.remove(position);
+
+        self.stockpile.put_all(&project.paid);
+
+        Some(project)
+    }
+}
+
+fn building_by_id(stronghold: &Stronghold, building: u32) -> Result<&Building, GameError> {
+    stronghold
+        .buildings
+        .iter()
+        .find(|b| b.id == building)
+        .ok_or_else(|| GameError::NoSuitableBuildingError(format!("No building {}", building)))
+}
+
+/// stop every task running in a building and put it back at the front of the queue, keeping its progress
+fn evict(stronghold: &mut Stronghold, building: u32) {
+    let evicted = stronghold
+        .active_tasks
+        .iter()
+        .filter(|task| task.building == Some(building))
+        .map(|task| task.id)
+        .collect::<Vec<u32>>();
+
+    for task_id in evicted.into_iter().rev() {
+        if let Some(task) = stronghold.stop_task(task_id) {
+            stronghold.task_queue.insert(0, task);
+        }
+    }
+}
+
+/// pay for and queue planned work, each a build task, its project and what it costs. either every project starts or none do
+pub(crate) fn launch(stronghold: &mut Stronghold, planned: Vec<(Task, Project, Cost)>) -> Result<Vec<u32>, GameError> {
+    // check the work can be done before anything is paid
+    for (task, ..) in &planned {
+        match stronghold.staff_task(task) {
+            Err(BlockedReason::NoBuilding) => {
+                return Err(GameError::NoSuitableBuildingError(format!("Missing a {:?} to build from", task.required_building)))
+            }
+            Err(BlockedReason::NoElf(role)) => {
+                return Err(GameError::NoSuitableElfError(format!("Not enough elves who can work as {:?}", role)))
+            }
+            _ => {}
+        }
+    }
+
+    let total = planned.iter().fold(Cost::new(), |total, (.., cost)| total.and(cost));
+    let shortfall = stronghold.stockpile.shortfall(&total);
+
+    if !shortfall.is_empty() {
+        return Err(GameError::ShortfallError(shortfall));
+    }
+
+    let mut started = Vec::new();
+
+    for (task, mut project, cost) in planned {
+        project.paid = stronghold.stockpile.pay(&cost)?;
+        project.task = stronghold.next_task_id;
+
+        stronghold.new_task(task)?;
+
+        started.push(project.task);
+        stronghold.projects.push(project);
+    }
+
+    Ok(started)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn material(resource_type: ResourceType) -> Object {
+        Object {
+            resource_type,
+            object_type: ObjectType::RawMaterial {
+                name: format!("Test {:?}", resource_type),
+                description: "Building material".to_string(),
+            },
+        }
+    }
+
+    /// the first stronghold of a new world, with an idle builder
+    fn stronghold() -> Stronghold {
+        let mut world = World::new(None, None, Some(3));
+        let mut stronghold = world.colonies.remove(0).strongholds.remove(0);
+
+        let elf = &mut stronghold.elves[0];
+        elf.task = None;
+        elf.roles.push(RoleAbility {
+            role: Role::Builder,
+            ability: AttributeLevel::Average,
+            experience: 0,
+        });
+
+        stronghold
+    }
+
+    #[test]
+    fn projects_start_together_or_not_at_all() {
+        let mut stronghold = stronghold();
+
+        // enough for the meeting hall's second level, but not the barracks' as well
+        let hall = building_cost(BuildingType::MeetingHall, 2);
+        for resource in &hall.resources {
+            stronghold.stockpile.put(material(resource.resource), resource.amount);
+        }
+
+        let stockpile = stronghold.stockpile.stacks().cloned().collect::<Vec<Stack>>();
+        let (queued, next_task_id) = (stronghold.task_queue.len(), stronghold.next_task_id);
+
+        let result = stronghold.start_projects(vec![(BuildingType::MeetingHall, Some(0)), (BuildingType::Barracks, Some(1))]);
+
+        assert!(matches!(result, Err(GameError::ShortfallError(_))));
+        assert_eq!(stronghold.stockpile.stacks().cloned().collect::<Vec<Stack>>(), stockpile);
+        assert!(stronghold.projects.is_empty());
+        assert_eq!(stronghold.task_queue.len(), queued);
+        assert_eq!(stronghold.next_task_id, next_task_id);
+
+        // on its own the meeting hall can be paid for
+        stronghold.upgrade(0).unwrap();
+        assert_eq!(stronghold.projects.len(), 1);
+    }
+
+    #[test]
+    fn demolished_building_ids_are_never_reused() {
+        let mut stronghold = stronghold();
+
+        for resource in &building_cost(BuildingType::Farm, 1).resources {
+            stronghold.stockpile.put(material(resource.resource), resource.amount);
+        }
+
+        let last = stronghold.buildings.iter().map(|building| building.id).max().unwrap();
+        stronghold.demolish(last).unwrap();
+
+        let task = stronghold.build(BuildingType::Farm).unwrap();
+        let id = stronghold.finish_project(task).unwrap();
+
+        assert!(id > last);
+        assert_eq!(stronghold.next_building_id, id + 1);
+        assert_eq!(stronghold.buildings.iter().filter(|building| building.id == id).count(), 1);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{defs::*, game::*};
+
+impl Cost {
+    pub fn new() -> Self {
+        Cost::default()
+    }
+
+    /// a cost of one resource
+    pub fn resource(resource: ResourceType, amount: u32) -> Self {
+        Cost::new().and_resource(resource, amount)
+    }
+
+    /// a cost of particular objects
+    pub fn objects(objects: &[Stack]) -> Self {
+        Cost::new().and_objects(objects)
+    }
+
+    /// add an amount of a resource, merging with any of it already in the cost
+    pub fn and_resource(mut self, resource: ResourceType, amount: u32) -> Self {
+        if amount == 0 {
+            return self;
+        }
+
+        match self.resources.iter_mut().find(|cost| cost.resource == resource) {
+            Some(cost) => cost.amount += amount,
+            None => self.resources.push(ResourceCost { resource, amount }),
+        }
+
+        self
+    }
+
+    /// add particular objects, merging with any of them already in the cost
+    pub fn and_objects(mut self, objects: &[Stack]) -> Self {
+        for stack in objects.iter().filter(|stack| stack.count > 0) {
+            match self.objects.iter_mut().find(|s| s.object == stack.object) {
+                Some(s) => s.count += stack.count,
+                None => self.objects.push(stack.clone()),
+            }
+        }
+
+        self
+    }
+
+    /// both costs together
+    pub fn and(self, other: &Cost) -> Self {
+        let cost = other
+            .resources
+            .iter()
+            .fold(self, |cost, resource| cost.and_resource(resource.resource, resource.amount));
+
+        cost.and_objects(&other.objects)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty() && self.objects.is_empty()
+    }
+}
+
+impl Inventory {
+    /// what is missing to pay a cost (empty if it can be paid). particular objects are paid first, so they don't count towards resource amounts
+    pub fn shortfall(&self, cost: &Cost) -> Cost {
+        let mut shortfall = Cost::new().and_objects(&self.missing(&cost.objects));
+
+        for resource in &Cost::new().and(cost).resources {
+            let used = cost
+                .objects
+                .iter()
+                .filter(|stack| stack.object.resource_type == resource.resource)
+                .map(|stack| stack.count.min(self.count(&stack.object)))
+                .sum::<u32>();
+
+            let available = self.count_resource(resource.resource).saturating_sub(used);
+
+            shortfall = shortfall.and_resource(resource.resource, resource.amount.saturating_sub(available));
+        }
+
+        shortfall
+    }
+
+    pub fn can_afford(&self, cost: &Cost) -> bool {
+        self.shortfall(cost).is_empty()
+    }
+
+    /// take exactly a cost out of the inventory, or nothing at all if it can't be paid. returns the objects taken, so they can be given back
+    pub fn pay(&mut self, cost: &Cost) -> Result<Vec<Stack>, GameError> {
+        let shortfall = self.shortfall(cost);
+
+        if !shortfall.is_empty() {
+            return Err(GameError::ShortfallError(shortfall));
+        }
+
+        self.take_all(&cost.objects);
+
+        let mut paid = cost.objects.clone();
+
+        for resource in &cost.resources {
+            paid.extend(self.take_resource(resource.resource, resource.amount).unwrap_or_default());
+        }
+
+        Ok(paid)
+    }
+}
@@ -0,0 +1,37 @@
+use crate::{defs::*, game::*, needs::*, stats::*};
+
+pub trait Mortality {
+    fn bury_dead(&mut self, tick: u64) -> Result<Vec<GameEvent>, GameError>;
+}
+
+impl Mortality for Stronghold {
+    /// remove every elf whose health has run out, putting their task back on the queue and their equipment in the stockpile, and report each death
+    fn bury_dead(&mut self, tick: u64) -> Result<Vec<GameEvent>, GameError> {
+        let mut events = Vec::new();
+
+        let mut i = 0;
+        while i < self.elves.len() {
+            if self.elves[i].health > 0 {
+                i += 1;
+                continue;
+            }
+
+            // unfinished work goes to the front of the queue for someone else to pick up, and anything held for it back to the stockpile
+            let mut elf = leave(self, i);
+
+            // what the elf had equipped goes back to the stockpile
+            for object in elf.equipment.strip() {
+                self.stockpile.put(object, 1);
+            }
+
+            events.push(GameEvent::Death {
+                tick,
+                stronghold: self.name.clone(),
+                elf: elf.name.join(" "),
+                cause: critical_needs(&elf).first().copied().unwrap_or(DeathCause::Injury),
+            });
+        }
+
+        Ok(events)
+    }
+}
@@ -1,5 +1,7 @@
+use crate::rng::GameRng;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Copy)]
 pub enum AttributeLevel {
@@ -84,7 +86,7 @@ pub enum Role {
 }
 
 /// Resource types describe the materials that objects are made from
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ResourceType {
     Wood,
     Stone,
@@ -160,7 +162,7 @@ pub enum ObjectType {
         name: String,
         description: String,
 
-        /// base defense of armor (further affected by strength)
+        /// defense of armor, taken off the damage of every hit
         defense_power: u32,
 
         /// how much agility is required to wear
@@ -194,8 +196,46 @@ pub struct Object {
     pub resource_type: ResourceType,
 }
 
+/// a number of one object
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Stack {
+    pub object: Object,
+    pub count: u32,
+}
+
+/// a number of objects of a resource type, whatever they are
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResourceCost {
+    pub resource: ResourceType,
+    pub amount: u32,
+}
+
+/// What something costs out of the stockpile: amounts of resources (ex. 3 wood + 1 iron) and particular objects
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Cost {
+    pub resources: Vec<ResourceCost>,
+    pub objects: Vec<Stack>,
+}
+
+/// Objects counted by kind rather than stored one by one. identical objects share a stack, so an object with state of its own (ex. a worn down pick axe) only shares with others in the same state. saved as its list of stacks
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(from = "Vec<Stack>", into = "Vec<Stack>")]
+pub struct Inventory {
+    /// every kind of object ever held, in the order first put in. emptied stacks are kept so the index stays valid
+    pub(crate) stacks: Vec<Stack>,
+
+    /// position of each object's stack
+    pub(crate) index: HashMap<Object, usize>,
+
+    /// how many objects of each resource type are held
+    pub(crate) resources: HashMap<ResourceType, u32>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Task {
+    /// task ID (unique within the stronghold, given out by new_task)
+    pub id: u32,
+
     /// name and synopsis of task
     pub description: String,
 
@@ -205,14 +245,214 @@ pub struct Task {
     /// required building for task
     pub required_building: BuildingType,
 
-    /// required roles workers must have to perform task
-    pub required_roles: Vec<Role>,
+    /// lowest level of the required building the task can be done at
+    pub required_level: u32,
+
+    /// workers needed to perform task, by role. each worker covers one role
+    pub required_roles: Vec<RoleRequirement>,
 
     /// required objects that must be present in the stockpile to perform task
-    pub required_objects: Vec<Object>,
+    pub required_objects: Vec<Stack>,
 
     /// objects that will be produced by task
-    pub produced_objects: Vec<Object>,
+    pub produced_objects: Vec<Stack>,
+
+    /// who may be given the task
+    pub assignee: Assignee,
+
+    /// higher priority tasks are staffed first
+    pub priority: TaskPriority,
+
+    /// where the task is in its life
+    pub state: TaskState,
+
+    /// why a queued or blocked task is waiting (None once it has been assigned)
+    pub blocked_reason: Option<BlockedReason>,
+
+    /// paused tasks stay in the queue until resumed
+    pub paused: bool,
+
+    /// elves working on the task, and the role each covers (empty while queued)
+    pub workers: Vec<Worker>,
+
+    /// ID of the building the task is being done at (None while queued)
+    pub building: Option<u32>,
+
+    /// tick the task was started (None until every worker has turned up)
+    pub started: Option<u64>,
+
+    /// ticks worked on the task so far. kept if the task is stopped, so it picks up where it left off
+    pub progress: u32,
+
+    /// required objects taken out of the stockpile when the task was assigned, consumed when it starts and returned if it is dropped before then
+    pub reserved_objects: Vec<Stack>,
+
+    /// whether the required objects have been used up, which happens the first time the task starts. a task stopped after that never pays for them again
+    pub consumed: bool,
+}
+
+/// how urgently a task should be staffed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TaskPriority {
+    Low,
+    Normal,
+    High,
+    Urgent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TaskState {
+    /// waiting for busy elves or a full building to free up
+    Queued,
+
+    /// staffed, waiting for every worker to turn up
+    Assigned,
+    InProgress,
+
+    /// waiting on something that won't sort itself out (see blocked_reason)
+    Blocked,
+    Done,
+    Cancelled,
+}
+
+/// why a task isn't being worked on
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BlockedReason {
+    /// there is no building of the required type (and level)
+    NoBuilding,
+
+    /// every building of the required type is running as many tasks as its level allows
+    BuildingFull,
+
+    /// not enough eligible elves have this role
+    NoElf(Role),
+
+    /// enough eligible elves have this role, but not enough are free
+    ElvesBusy(Role),
+
+    /// these required objects aren't in the stockpile
+    MissingObjects(Vec<Stack>),
+
+    /// the task is assigned to a work group that doesn't exist
+    NoSuchGroup(u32),
+
+    Paused,
+}
+
+/// how many workers of a role a task needs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoleRequirement {
+    pub role: Role,
+    pub workers: u32,
+}
+
+/// an elf working on a task
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Worker {
+    /// ID of the elf
+    pub elf: u32,
+
+    /// required role the elf is covering
+    pub role: Role,
+}
+
+/// who a task can go to. whoever it is must still have the task's required roles
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Assignee {
+    /// any elf in the stronghold
+    Anyone,
+
+    /// one elf, by ID
+    Elf(u32),
+
+    /// any member of a work group, by ID
+    WorkGroup(u32),
+
+    /// any elf with the given profession
+    Profession(Role),
+}
+
+/// A group of elves of any roles, put together by the player. tasks assigned to it go to any eligible member
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkGroup {
+    /// group ID (unique within the stronghold)
+    pub id: u32,
+
+    pub name: String,
+
+    /// IDs of the elves in the group
+    pub members: Vec<u32>,
+}
+
+/// How to make something: what goes in, what comes out, and where and by whom it is made
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Recipe {
+    /// name of recipe (unique within a recipe book), used as the task description
+    pub name: String,
+
+    /// objects used up
+    pub inputs: Vec<Stack>,
+
+    /// objects made
+    pub outputs: Vec<Stack>,
+
+    /// building the recipe is made at
+    pub building: BuildingType,
+
+    /// lowest level of the building that can make it
+    pub min_level: u32,
+
+    /// workers needed, by role
+    pub roles: Vec<RoleRequirement>,
+
+    /// base duration in ticks, before worker skill is factored in
+    pub duration: u32,
+}
+
+/// A building being put up or upgraded. the level only changes once its build task is done
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Project {
+    /// ID of the build task
+    pub task: u32,
+
+    pub building_type: BuildingType,
+
+    /// ID of the building being upgraded or repurposed (None for a new building)
+    pub building: Option<u32>,
+
+    /// level the building will be once done
+    pub level: u32,
+
+    /// what was taken from the stockpile for it, given back if it is cancelled
+    pub paid: Vec<Stack>,
+}
+
+/// when a standing order puts in a new task
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum OrderKind {
+    /// as soon as the last one is done, forever
+    Repeat,
+
+    /// whenever the stockpile holds fewer than `target` of an object
+    KeepStocked { object: Object, target: u32 },
+}
+
+/// An order the stronghold keeps on its own, queueing copies of a task when it is due
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StandingOrder {
+    /// order ID (unique within the stronghold)
+    pub id: u32,
+
+    /// task copied each time the order is due (its ID is replaced by new_task)
+    pub task: Task,
+
+    pub kind: OrderKind,
+
+    /// a paused order queues nothing new, but leaves its current task be
+    pub paused: bool,
+
+    /// ID of the task last queued for the order, while it is queued or underway
+    pub current_task: Option<u32>,
 }
 
 /// describes how well an elf can perform a task
@@ -220,11 +460,26 @@ pub struct Task {
 pub struct RoleAbility {
     pub role: Role,
     pub ability: AttributeLevel,
+
+    /// experience towards the next ability level, earned by finishing tasks
+    pub experience: u32,
+}
+
+/// how long an elf has held a role
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct RoleTenure {
+    pub role: Role,
+
+    /// ticks spent holding the role
+    pub ticks: u64,
 }
 
 /// An elf in the colony
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Elf {
+    /// elf ID, unique within its stronghold (given out by the stronghold when the elf joins)
+    pub id: u32,
+
     /// name of elves (can be multiple names, ex: first, middle, last)
     pub name: Vec<String>,
 
@@ -261,17 +516,44 @@ pub struct Elf {
     /// sleep level (affects happiness) (excellent = well rested, terrible = exhausted). all elves need to sleep for 8 hours a day, but when this happens is irrelevant (usually when there are no tasks and their sleep level is Poor or Terrible, or when their sleep level is Terrible regardless of tasks)
     pub sleep: AttributeLevel,
 
+    /// whether the elf is currently asleep (asleep elves don't take tasks, eat or drink)
+    pub asleep: bool,
+
+    /// how long the elf has held each status role (elder, leader), kept after the role is lost
+    pub tenure: Vec<RoleTenure>,
+
     /// roles in the colony (affects behavior and stats)
     pub roles: Vec<RoleAbility>,
 
-    /// current task (affects behavior and stats)
-    pub task: Option<Task>,
-
-    /// tick when current task was started
-    pub task_start: Option<u64>,
+    /// ID of current task (affects behavior and stats)
+    pub task: Option<u32>,
 
     /// current health level (affects behavior and stats), 0 = dead, 100 = full health
     pub health: i32, 
+
+    /// what the elf is carrying and wearing
+    pub equipment: Equipment,
+}
+
+/// where on an elf a piece of equipment goes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum EquipmentSlot {
+    /// weapons
+    MainHand,
+
+    /// armor
+    BodyArmor,
+
+    /// pick axes
+    Tool,
+}
+
+/// An elf's equipment, taken from and returned to the stockpile
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Equipment {
+    pub main_hand: Option<Object>,
+    pub body_armor: Option<Object>,
+    pub tool: Option<Object>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Copy)]
@@ -332,19 +614,25 @@ pub enum BuildingType {
 
     /// traders
     TradingPost,
+
+    /// beds for elves to sleep in
+    Dormitory,
 }
 
 /// Buildings are required for tasks, ex. a blacksmith requires a forge, a cook requires a kitchen, etc. Buildings can be upgraded to improve efficiency, capacity, etc. Buildings can be destroyed by enemies, natural disasters, or elves rebelling. They are also needed for defense, ex. walls, towers, etc.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Building {
-    /// building ID (unique identifier)
+    /// building ID (unique within the stronghold, and never reused)
     pub id: u32,
 
-    /// level of building (affects efficiency, capacity, etc.)
+    /// level of building (affects efficiency, capacity, etc.). a building can run as many tasks at once as its level
     pub level: u32,
 
     /// type of building (affects tasks, defense, etc.)
     pub building_type: BuildingType,
+
+    /// hit points left (see max_health). the building falls at 0
+    pub health: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -361,8 +649,76 @@ pub struct Stronghold {
     /// list of all tasks currently awaiting a worker
     pub task_queue: Vec<Task>,
 
-    /// list of all items in stockpile
-    pub stockpile: Vec<Object>,
+    /// tasks that have been staffed, and are starting or underway
+    pub active_tasks: Vec<Task>,
+
+    /// most recently finished or cancelled tasks, oldest first
+    pub task_log: Vec<Task>,
+
+    /// ID the next task will get
+    pub next_task_id: u32,
+
+    /// buildings under construction or being upgraded
+    pub projects: Vec<Project>,
+
+    /// ID the next building to be put up will get. IDs are never reused, even once a building is demolished
+    pub next_building_id: u32,
+
+    /// every item in the stockpile, counted by kind
+    pub stockpile: Inventory,
+
+    /// ID the next elf to join will get
+    pub next_elf_id: u32,
+
+    /// groups of elves put together by the player to work on tasks
+    pub work_groups: Vec<WorkGroup>,
+
+    /// ID the next work group will get
+    pub next_work_group_id: u32,
+
+    /// orders that keep tasks coming without the player queueing each one
+    pub standing_orders: Vec<StandingOrder>,
+
+    /// ID the next standing order will get
+    pub next_order_id: u32,
+
+    /// stops every standing order in the stronghold at once
+    pub orders_paused: bool,
+
+    /// levels of the stronghold itself
+    pub stats: StrongholdStats,
+}
+
+/// Levels of a stronghold as a whole, each bought with upgrade_stat
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct StrongholdStats {
+    /// caps how many elves and buildings the stronghold can hold
+    pub size: u32,
+
+    /// shortens travel from the stronghold to others
+    pub movement: u32,
+
+    /// strengthens the defence given by walls and towers
+    pub fortification: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum StrongholdStat {
+    Size,
+    Movement,
+    Fortification,
+}
+
+/// An elf on the road between two strongholds of a colony
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Traveller {
+    pub elf: Elf,
+
+    /// name of the stronghold the elf is heading to
+    pub destination: String,
+
+    /// tick the elf gets there
+    pub arrives: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -375,6 +731,9 @@ pub struct Colony {
 
     /// owner of colony's username (currently unimplemented, but left in for future multiplayer functionality)
     pub leader: String,
+
+    /// elves moving between the colony's strongholds
+    pub travellers: Vec<Traveller>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -387,8 +746,254 @@ pub struct World {
 
     /// current in game tick (twenty ticks per second)
     pub tick: u64,
+
+    /// seed the world was generated from
+    pub seed: u64,
+
+    /// random number generator every random event in the world draws from, so the same seed and the same commands give the same world
+    pub rng: GameRng,
+
+    /// log of notable things that have happened in the world, oldest first
+    pub events: Vec<GameEvent>,
+
+    /// fights underway, and the most recently finished ones
+    pub encounters: Vec<Encounter>,
+
+    /// ID the next encounter will get
+    pub next_encounter_id: u32,
+
+    /// goblin strongholds on the map
+    pub goblin_strongholds: Vec<GoblinStronghold>,
+}
+
+/// A goblin stronghold, growing on what its goblins scavenge and raiding the strongholds of the colony it borders
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GoblinStronghold {
+    pub name: String,
+
+    /// name of the colony whose strongholds it raids
+    pub colony: String,
+
+    /// goblins at home
+    pub goblins: Vec<Goblin>,
+    pub stockpile: Inventory,
+
+    /// goblins away raiding, in the order they fight in the raid's battle
+    pub raiders: Vec<Goblin>,
+
+    /// ID of the raid's encounter while one is underway
+    pub raid: Option<u32>,
+
+    /// tick the last raid set out (or the stronghold was founded)
+    pub last_raid: u64,
+}
+
+/// which side of an encounter a combatant fights on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Attackers,
+    Defenders,
+}
+
+/// One fighter in an encounter, with the stats it fights with
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Combatant {
+    pub name: String,
+    pub side: Side,
+    pub strength: AttributeLevel,
+    pub agility: AttributeLevel,
+    pub health: i32,
+
+    /// how well the combatant holds its nerve in a battle as its side falls
+    pub loyalty: AttributeLevel,
+
+    /// weapon fought with (bare hands if None)
+    pub weapon: Option<Object>,
+    pub armor: Option<Object>,
+
+    /// the stronghold name and ID of the elf this is, so its wounds carry over (None for goblins)
+    pub elf: Option<(String, u32)>,
+
+    /// a combatant that has left the fight (or whose elf is gone) no longer fights
+    pub withdrawn: bool,
+}
+
+/// one attack in an encounter
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CombatRoll {
+    pub round: u32,
+    pub attacker: String,
+    pub defender: String,
+    pub dodge_roll: f32,
+    pub dodged: bool,
+    pub crit_roll: f32,
+    pub crit: bool,
+    pub damage: i32,
+
+    /// damage the defender's armor took off the hit
+    pub blocked: i32,
+
+    /// defender's health after the attack
+    pub health: i32,
+}
+
+/// how an encounter ended
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// the other side has nobody left standing
+    Victory(Side),
+
+    /// this side ran
+    Retreat(Side),
+}
+
+/// A fight that plays out one exchange per tick, so it can be watched, joined or fled while it runs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Encounter {
+    /// encounter ID (unique within the world)
+    pub id: u32,
+
+    /// name of the stronghold the fight is at
+    pub stronghold: String,
+    pub combatants: Vec<Combatant>,
+
+    /// exchanges fought so far
+    pub round: u32,
+
+    /// every attack, oldest first
+    pub log: Vec<CombatRoll>,
+
+    /// None while the fight is underway
+    pub outcome: Option<Outcome>,
+
+    /// set when this is a battle between forces rather than a duel
+    pub battle: Option<Battle>,
+}
+
+/// A battle: everyone on both sides fights every exchange, and the defending stronghold's walls and towers join in
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Battle {
+    /// name of the stronghold the attackers came from, which gets what they carry off (None if they have nowhere to take it)
+    pub home: Option<String>,
+
+    /// None until the battle is over
+    pub report: Option<BattleReport>,
+}
+
+/// what a battle cost and won
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BattleReport {
+    pub outcome: Outcome,
+    pub rounds: u32,
+
+    /// combatants killed
+    pub casualties: Vec<Combatant>,
+
+    /// combatants who ran (or were pulled out) alive
+    pub fled: Vec<Combatant>,
+
+    /// plunder carried off by victorious attackers, or weapons and armor taken from fallen attackers by victorious defenders
+    pub loot: Vec<Stack>,
+    pub buildings_damaged: Vec<DamagedBuilding>,
+}
+
+/// a building hit while victorious attackers sacked a stronghold
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DamagedBuilding {
+    pub building: u32,
+    pub building_type: BuildingType,
+    pub damage: u32,
+    pub destroyed: bool,
+}
+
+/// what killed an elf
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DeathCause {
+    /// hunger stayed critical
+    Starvation,
+
+    /// thirst stayed critical
+    Dehydration,
+
+    /// happiness stayed critical
+    Despair,
+
+    /// health ran out with no need critical
+    Injury,
+}
+
+/// Notable things that happen in the world, kept for the player to read back
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    Death {
+        /// tick the elf died on
+        tick: u64,
+
+        /// name of the stronghold the elf lived in
+        stronghold: String,
+
+        /// full name of the elf
+        elf: String,
+
+        cause: DeathCause,
+    },
+    BuildingDamaged {
+        tick: u64,
+        stronghold: String,
+
+        /// ID of the building
+        building: u32,
+        building_type: BuildingType,
+        damage: u32,
+        cause: DamageCause,
+    },
+    BuildingDestroyed {
+        tick: u64,
+        stronghold: String,
+        building: u32,
+        building_type: BuildingType,
+        cause: DamageCause,
+    },
+
+    EncounterEnded {
+        tick: u64,
+
+        /// ID of the encounter
+        encounter: u32,
+        stronghold: String,
+        outcome: Outcome,
+    },
+
+    /// goblins set out to raid a stronghold
+    Raid {
+        tick: u64,
+
+        /// name of the goblin stronghold
+        goblins: String,
+        stronghold: String,
+        raiders: u32,
+
+        /// ID of the raid's encounter
+        encounter: u32,
+    },
+
+    /// a stronghold lost its last building. its elves and stock went to another stronghold of the colony, if there was one
+    StrongholdDisbanded {
+        tick: u64,
+        stronghold: String,
+        moved_to: Option<String>,
+    },
 }
 
+/// what damaged a building
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DamageCause {
+    Combat,
+    Fire,
+    Storm,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Goblin {
     /// name of goblin
     pub name: Vec<String>,
@@ -413,12 +1018,11 @@ pub struct Goblin {
 }
 
 pub trait Random {
-    fn random() -> Self;
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self;
 }
 
 impl Random for Role {
-    fn random() -> Role {
-        let mut rng = rand::thread_rng();
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Role {
         match rng.gen_range(3..=19) {
             3 => Role::Warrior,
             4 => Role::Farmer,
@@ -442,8 +1046,7 @@ impl Random for Role {
 }
 
 impl Random for AttributeLevel {
-    fn random() -> AttributeLevel {
-        let mut rng = rand::thread_rng();
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> AttributeLevel {
         match rng.gen_range(0..5) {
             0 => AttributeLevel::Excellent,
             1 => AttributeLevel::Good,
@@ -455,6 +1058,68 @@ impl Random for AttributeLevel {
     }
 }
 
+impl Task {
+    /// a task for anyone with the required roles, at normal priority, needing and producing nothing
+    pub fn new(
+        description: String,
+        duration: u32,
+        required_building: BuildingType,
+        required_roles: Vec<RoleRequirement>,
+    ) -> Task {
+        Task {
+            id: 0,
+            description,
+            duration,
+            required_building,
+            required_level: 1,
+            required_roles,
+            required_objects: Vec::new(),
+            produced_objects: Vec::new(),
+            assignee: Assignee::Anyone,
+            priority: TaskPriority::Normal,
+            state: TaskState::Queued,
+            blocked_reason: None,
+            paused: false,
+            workers: Vec::new(),
+            building: None,
+            started: None,
+            progress: 0,
+            reserved_objects: Vec::new(),
+            consumed: false,
+        }
+    }
+}
+
+impl BlockedReason {
+    /// whether the task is just waiting its turn, rather than stuck until something changes
+    pub fn is_transient(&self) -> bool {
+        matches!(self, BlockedReason::BuildingFull | BlockedReason::ElvesBusy(_))
+    }
+}
+
+impl AttributeLevel {
+    /// level from its numeric value, clamped to Terrible..=Excellent
+    pub fn from_value(value: i32) -> AttributeLevel {
+        match value {
+            i32::MIN..=1 => AttributeLevel::Terrible,
+            2 => AttributeLevel::Poor,
+            3 => AttributeLevel::Average,
+            4 => AttributeLevel::Good,
+            _ => AttributeLevel::Excellent,
+        }
+    }
+
+    /// go up by a number of levels, stopping at Excellent
+    pub fn raise(self, levels: u32) -> AttributeLevel {
+        AttributeLevel::from_value((self as i32).saturating_add(levels as i32))
+    }
+
+    /// go down by a number of levels, stopping at Terrible
+    pub fn lower(self, levels: u32) -> AttributeLevel {
+        AttributeLevel::from_value((self as i32).saturating_sub(levels as i32))
+    }
+}
+
 pub fn profession_to_building(profession: Role) -> BuildingType {
     match profession {
         Role::Elder => BuildingType::MeetingHall,
@@ -501,7 +1166,7 @@ pub fn building_to_profession(building: BuildingType) -> Role {
         BuildingType::TradingPost => Role::Trader,
         BuildingType::Tower => Role::Warrior,
         BuildingType::Wall => Role::Warrior,
-        _ => Role::Nitwit,
+        BuildingType::Dormitory => Role::Builder,
     }
 }
 
@@ -0,0 +1,243 @@
+use crate::{construction::*, defs::*, game::*, impls::*, needs::*, rng::*, stats::*};
+use rand::Rng;
+
+/// hit points of each building level
+pub const HEALTH_PER_LEVEL: u32 = 100;
+
+/// base ticks of work to repair each hit point
+pub const REPAIR_TICKS_PER_HEALTH: u32 = 6;
+
+/// chance each in game hour of a disaster striking a stronghold (about one every two days)
+pub const DISASTER_CHANCE: f64 = 1.0 / 48.0;
+
+/// most damage a disaster does to the building it hits
+pub const DISASTER_DAMAGE: u32 = 60;
+
+/// hit points of an undamaged building of a level
+pub fn max_health(level: u32) -> u32 {
+    HEALTH_PER_LEVEL * level
+}
+
+pub trait Durability {
+    fn damage_building(
+        &mut self,
+        building: u32,
+        damage: u32,
+        cause: DamageCause,
+        tick: u64,
+    ) -> Result<Vec<GameEvent>, GameError>;
+    fn repair_role(&self, building_type: BuildingType) -> Role;
+    fn repair(&mut self, building: u32) -> Result<u32, GameError>;
+    fn disasters(&mut self, tick: u64, rng: &mut GameRng) -> Result<Vec<GameEvent>, GameError>;
+}
+
+impl Durability for Stronghold {
+    /// damage a building, knocking it down once it has no hit points left. walls and towers soften attacks (see defence)
+    fn damage_building(
+        &mut self,
+        building: u32,
+        damage: u32,
+        cause: DamageCause,
+        tick: u64,
+    ) -> Result<Vec<GameEvent>, GameError> {
+        let damage = match cause {
+            DamageCause::Combat => damage * 100 / (100 + self.defence()),
+            _ => damage,
+        };
+
+        let target = self
+            .buildings
+            .iter_mut()
+            .find(|b| b.id == building)
+            .ok_or_else(|| GameError::NoSuitableBuildingError(format!("No building {}", building)))?;
+
+        let damage = damage.min(target.health);
+        target.health -= damage;
+
+        let building_type = target.building_type;
+        let destroyed = target.health == 0;
+
+        let mut events = vec![GameEvent::BuildingDamaged {
+            tick,
+            stronghold: self.name.clone(),
+            building,
+            building_type,
+            damage,
+            cause,
+        }];
+
+        if destroyed {
+            self.demolish(building)?;
+
+            events.push(GameEvent::BuildingDestroyed {
+                tick,
+                stronghold: self.name.clone(),
+                building,
+                building_type,
+                cause,
+            });
+        }
+
+        Ok(events)
+    }
+
+    /// repairs are done by the craft of the building's main material (carpenters for wood, stonemasons for stone, blacksmiths for iron), or builders if there are none
+    fn repair_role(&self, building_type: BuildingType) -> Role {
+        let craft = match building_materials(building_type).first().map(|material| material.resource) {
+            Some(ResourceType::Wood) => Role::Carpenter,
+            Some(ResourceType::Stone) => Role::Stonemason,
+            Some(ResourceType::Iron) => Role::Blacksmith,
+            _ => Role::Builder,
+        };
+
+        if self.elves.iter().any(|elf| role_ability(elf, &craft).is_some()) {
+            craft
+        } else {
+            Role::Builder
+        }
+    }
+
+    /// start repairing a damaged building, returning the repair task ID. each level's worth of lost hit points costs a level's materials
+    fn repair(&mut self, building: u32) -> Result<u32, GameError> {
+        if self.projects.iter().any(|project| project.building == Some(building)) {
+            return Err(GameError::ProjectExistsError(format!("Building {} is already being worked on", building)));
+        }
+
+        let target = self
+            .buildings
+            .iter()
+            .find(|b| b.id == building)
+            .ok_or_else(|| GameError::NoSuitableBuildingError(format!("No building {}", building)))?;
+
+        let lost = max_health(target.level) - target.health;
+
+        if lost == 0 {
+            return Err(GameError::NoSuitableBuildingError(format!("Building {} isn't damaged", building)));
+        }
+
+        let task = Task::new(
+            format!("Repair {:?} {}", target.building_type, building),
+            lost * REPAIR_TICKS_PER_HEALTH,
+            self.build_site(),
+            vec![RoleRequirement {
+                role: self.repair_role(target.building_type),
+                workers: 1,
+            }],
+        );
+
+        // the building stays as it is, and is back to full health once done
+        let project = Project {
+            task: 0,
+            building_type: target.building_type,
+            building: Some(building),
+            level: target.level,
+            paid: Vec::new(),
+        };
+
+        let cost = building_cost(target.building_type, lost.div_ceil(HEALTH_PER_LEVEL));
+
+        Ok(launch(self, vec![(task, project, cost)])?[0])
+    }
+
+    /// now and then a fire or storm strikes a random building
+    fn disasters(&mut self, tick: u64, rng: &mut GameRng) -> Result<Vec<GameEvent>, GameError> {
+        if tick == 0 || !tick.is_multiple_of(TICKS_PER_HOUR) || self.buildings.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if !rng.gen_bool(DISASTER_CHANCE) {
+            return Ok(Vec::new());
+        }
+
+        let building = self.buildings[rng.gen_range(0..self.buildings.len())].id;
+        let cause = if rng.gen_bool(0.5) { DamageCause::Fire } else { DamageCause::Storm };
+        let damage = rng.gen_range(1..=DISASTER_DAMAGE);
+
+        self.damage_building(building, damage, cause, tick)
+    }
+}
+
+/// disband every stronghold in a colony that has lost its last building. its stock moves to the colony's first remaining stronghold, if it has one, and its surviving elves set off for it.
+/// with nowhere left to go they stay on the road, and make for the first stronghold the colony has again (see arrivals)
+pub fn disband_fallen(colony: &mut Colony, tick: u64) -> Result<Vec<GameEvent>, GameError> {
+    let mut events = Vec::new();
+
+    while let Some(position) = colony.strongholds.iter().position(|stronghold| stronghold.buildings.is_empty()) {
+        let mut fallen = colony.strongholds.remove(position);
+
+        // nothing can be worked on any more. cancelling gives back anything held or paid for
+        let task_ids = fallen
+            .task_queue
+            .iter()
+            .chain(fallen.active_tasks.iter())
+            .map(|task| task.id)
+            .collect::<Vec<u32>>();
+
+        for task_id in task_ids {
+            fallen.cancel_task(task_id)?;
+        }
+
+        let refuge = colony.strongholds.iter_mut().find(|stronghold| !stronghold.buildings.is_empty());
+
+        events.push(GameEvent::StrongholdDisbanded {
+            tick,
+            stronghold: fallen.name.clone(),
+            moved_to: refuge.as_ref().map(|stronghold| stronghold.name.clone()),
+        });
+
+        let destination = refuge.as_ref().map_or(fallen.name.clone(), |stronghold| stronghold.name.clone());
+        let arrives = tick + fallen.travel_ticks();
+
+        for mut elf in std::mem::take(&mut fallen.elves) {
+            elf.task = None;
+
+            colony.travellers.push(Traveller {
+                elf,
+                destination: destination.clone(),
+                arrives,
+            });
+        }
+
+        if let Some(refuge) = refuge {
+            let stock = fallen.stockpile.stacks().cloned().collect::<Vec<Stack>>();
+            refuge.stockpile.put_all(&stock);
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elves_of_a_colony_last_stronghold_wait_on_the_road() {
+        let mut world = World::new(None, None, Some(19));
+        let mut refuge = World::new(None, None, Some(23)).colonies.remove(0).strongholds.remove(0);
+        let colony = &mut world.colonies[0];
+
+        let elves = colony.strongholds[0].elves.len();
+        colony.strongholds[0].buildings.clear();
+
+        let events = disband_fallen(colony, 0).unwrap();
+
+        assert!(colony.strongholds.is_empty());
+        assert!(matches!(&events[..], [GameEvent::StrongholdDisbanded { moved_to: None, .. }]));
+        assert_eq!(colony.travellers.len(), elves);
+
+        // they keep waiting while there is nowhere to go...
+        let arrives = colony.travellers[0].arrives;
+        arrivals(colony, arrives);
+        assert_eq!(colony.travellers.len(), elves);
+
+        // ...and join the colony's next stronghold
+        refuge.name = "Second Stronghold".to_string();
+        refuge.elves.truncate(1);
+        colony.strongholds.push(refuge);
+        arrivals(colony, arrives);
+
+        assert_eq!(colony.strongholds[0].elves.len() + colony.travellers.len(), elves + 1);
+        assert!(colony.strongholds[0].elves.len() > 1);
+    }
+}
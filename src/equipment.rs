@@ -0,0 +1,248 @@
+use crate::{combat::*, defs::*, game::*, groups::*};
+
+/// breaking power a miner's pick axe needs to bring up another load's worth of a mine task's output
+pub const BREAKING_POWER_PER_LOAD: u32 = 5;
+
+impl EquipmentSlot {
+    /// the slot a piece of equipment goes in (None if it isn't equipment)
+    pub fn of(object: &Object) -> Option<EquipmentSlot> {
+        match object.object_type {
+            ObjectType::Weapon { .. } => Some(EquipmentSlot::MainHand),
+            ObjectType::Armor { .. } => Some(EquipmentSlot::BodyArmor),
+            ObjectType::PickAxe { .. } => Some(EquipmentSlot::Tool),
+            _ => None,
+        }
+    }
+}
+
+impl Equipment {
+    pub fn slot(&self, slot: EquipmentSlot) -> Option<&Object> {
+        match slot {
+            EquipmentSlot::MainHand => self.main_hand.as_ref(),
+            EquipmentSlot::BodyArmor => self.body_armor.as_ref(),
+            EquipmentSlot::Tool => self.tool.as_ref(),
+        }
+    }
+
+    /// everything equipped
+    pub fn items(&self) -> impl Iterator<Item = &Object> {
+        self.main_hand.iter().chain(self.body_armor.iter()).chain(self.tool.iter())
+    }
+
+    /// take everything off, returning it
+    pub fn strip(&mut self) -> Vec<Object> {
+        [self.main_hand.take(), self.body_armor.take(), self.tool.take()]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// breaking power of the pick axe, if one is carried
+    pub fn breaking_power(&self) -> u32 {
+        match &self.tool {
+            Some(Object {
+                object_type: ObjectType::PickAxe { breaking_power, .. },
+                ..
+            }) => *breaking_power,
+            _ => 0,
+        }
+    }
+}
+
+impl Elf {
+    /// agility after every piece of equipment takes its penalty off
+    pub fn effective_agility(&self) -> AttributeLevel {
+        effective_agility(self.agility, self.equipment.items())
+    }
+}
+
+/// what a finished task gives. mine work brings up an extra load of its output for every BREAKING_POWER_PER_LOAD of its miners' pick axes
+pub fn task_output(task: &Task, elves: &[Elf]) -> Vec<Stack> {
+    if task.required_building != BuildingType::Mine {
+        return task.produced_objects.clone();
+    }
+
+    let breaking_power = task
+        .workers
+        .iter()
+        .filter(|worker| worker.role == Role::Miner)
+        .filter_map(|worker| elves.iter().find(|elf| elf.id == worker.elf))
+        .map(|elf| elf.equipment.breaking_power())
+        .sum::<u32>();
+
+    let loads = 1 + breaking_power / BREAKING_POWER_PER_LOAD;
+
+    task.produced_objects
+        .iter()
+        .map(|stack| Stack {
+            object: stack.object.clone(),
+            count: stack.count * loads,
+        })
+        .collect()
+}
+
+pub trait Equip {
+    fn equip(&mut self, elf: u32, object: &Object) -> Result<Option<Object>, GameError>;
+    fn unequip(&mut self, elf: u32, slot: EquipmentSlot) -> Result<Object, GameError>;
+}
+
+impl Equip for Stronghold {
+    /// give an elf a piece of equipment from the stockpile, returning what it replaces to the stockpile
+    fn equip(&mut self, elf: u32, object: &Object) -> Result<Option<Object>, GameError> {
+        let slot = EquipmentSlot::of(object)
+            .ok_or_else(|| GameError::EquipmentError(format!("{} isn't equipment", object.object_type.name())))?;
+
+        let index = self
+            .elf_index(elf)
+            .ok_or_else(|| GameError::NoSuitableElfError(format!("No elf {}", elf)))?;
+
+        // whatever is in the slot comes off, and everything the elf would then have on has to be usable under all their penalties
+        let worn = &self.elves[index];
+        let kept = worn
+            .equipment
+            .items()
+            .filter(|item| EquipmentSlot::of(item) != Some(slot))
+            .chain([object])
+            .collect::<Vec<&Object>>();
+
+        check_equipment(worn.agility, &kept)?;
+
+        if !self.stockpile.take(object, 1) {
+            return Err(GameError::ShortfallError(Cost::objects(&[Stack {
+                object: object.clone(),
+                count: 1,
+            }])));
+        }
+
+        let equipment = &mut self.elves[index].equipment;
+
+        let replaced = match slot {
+            EquipmentSlot::MainHand => equipment.main_hand.replace(object.clone()),
+            EquipmentSlot::BodyArmor => equipment.body_armor.replace(object.clone()),
+            EquipmentSlot::Tool => equipment.tool.replace(object.clone()),
+        };
+
+        if let Some(replaced) = &replaced {
+            self.stockpile.put(replaced.clone(), 1);
+        }
+
+        Ok(replaced)
+    }
+
+    /// take an elf's equipment out of a slot and put it back in the stockpile
+    fn unequip(&mut self, elf: u32, slot: EquipmentSlot) -> Result<Object, GameError> {
+        let index = self
+            .elf_index(elf)
+            .ok_or_else(|| GameError::NoSuitableElfError(format!("No elf {}", elf)))?;
+
+        let equipment = &mut self.elves[index].equipment;
+
+        let object = match slot {
+            EquipmentSlot::MainHand => equipment.main_hand.take(),
+            EquipmentSlot::BodyArmor => equipment.body_armor.take(),
+            EquipmentSlot::Tool => equipment.tool.take(),
+        }
+        .ok_or_else(|| GameError::EquipmentError(format!("Nothing in {:?}", slot)))?;
+
+        self.stockpile.put(object.clone(), 1);
+
+        Ok(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gear(
+        object_type: fn(AttributeLevel, AttributeLevel) -> ObjectType,
+        requirement: AttributeLevel,
+        penalty: AttributeLevel,
+    ) -> Object {
+        Object {
+            resource_type: ResourceType::Iron,
+            object_type: object_type(requirement, penalty),
+        }
+    }
+
+    fn armor(agility_requirement: AttributeLevel, agility_penalty: AttributeLevel) -> ObjectType {
+        ObjectType::Armor {
+            name: format!("Mail ({:?}, {:?})", agility_requirement, agility_penalty),
+            description: "Test armor".to_string(),
+            defense_power: 2,
+            agility_requirement,
+            agility_penalty,
+        }
+    }
+
+    fn weapon(agility_requirement: AttributeLevel, agility_penalty: AttributeLevel) -> ObjectType {
+        ObjectType::Weapon {
+            name: format!("Sword ({:?}, {:?})", agility_requirement, agility_penalty),
+            description: "Test weapon".to_string(),
+            damage_power: 2,
+            agility_requirement,
+            agility_penalty,
+        }
+    }
+
+    /// the first stronghold of a new world, with its first elf at an agility and the gear in stock
+    fn outfitter(world: &mut World, agility: AttributeLevel, gear: &[&Object]) -> u32 {
+        let stronghold = &mut world.colonies[0].strongholds[0];
+        stronghold.elves[0].agility = agility;
+
+        for object in gear {
+            stronghold.stockpile.put((*object).clone(), 1);
+        }
+
+        stronghold.elves[0].id
+    }
+
+    #[test]
+    fn requirements_count_every_slot_but_the_one_replaced() {
+        use AttributeLevel::*;
+
+        let light = gear(armor, Terrible, Average);
+        let fitted = gear(armor, Average, Terrible);
+        let sword = gear(weapon, Average, Terrible);
+
+        let mut world = World::new(None, None, Some(11));
+        let elf = outfitter(&mut world, Good, &[&light, &fitted, &sword]);
+        let stronghold = &mut world.colonies[0].strongholds[0];
+
+        // the light armor's penalty doesn't count against the armor replacing it...
+        stronghold.equip(elf, &light).unwrap();
+        assert_eq!(stronghold.equip(elf, &fitted).unwrap(), Some(light));
+
+        // ...but the fitted armor's does against a weapon
+        assert!(matches!(stronghold.equip(elf, &sword), Err(GameError::EquipmentError(_))));
+
+        // and a combatant made from the elf agrees
+        let mut combatant = Combatant::elf(stronghold, &stronghold.elves[0], Side::Defenders);
+        assert!(combatant.equip(gear(armor, Average, Terrible)).is_ok());
+        assert!(matches!(combatant.equip(sword), Err(GameError::EquipmentError(_))));
+    }
+
+    #[test]
+    fn armor_cannot_leave_a_weapon_unusable() {
+        use AttributeLevel::*;
+
+        let sword = gear(weapon, Good, Terrible);
+        let heavy = gear(armor, Terrible, Poor);
+
+        let mut world = World::new(None, None, Some(13));
+        let elf = outfitter(&mut world, Excellent, &[&sword, &heavy]);
+        let stronghold = &mut world.colonies[0].strongholds[0];
+
+        stronghold.equip(elf, &sword).unwrap();
+
+        // with the armor on as well the sword would need more agility than is left
+        assert!(matches!(stronghold.equip(elf, &heavy), Err(GameError::EquipmentError(_))));
+        assert_eq!(stronghold.stockpile.count(&heavy), 1);
+        assert_eq!(stronghold.elves[0].equipment.main_hand, Some(sword));
+        assert_eq!(stronghold.elves[0].equipment.body_armor, None);
+
+        let mut combatant = Combatant::elf(stronghold, &stronghold.elves[0], Side::Defenders);
+        assert!(matches!(combatant.equip(heavy), Err(GameError::EquipmentError(_))));
+        assert_eq!(combatant.armor, None);
+    }
+}
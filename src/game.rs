@@ -1,9 +1,5 @@
-use crate::{defs::*, impls::*, };
-use rand::Rng;
-use serde::{Deserialize, Serialize};
+use crate::{combat::*, construction::*, death::*, defs::*, durability::*, goblins::*, groups::*, happiness::*, impls::*, needs::*, orders::*, rng::*, save::*, stats::*};
 use serde_json;
-use std::fs::File;
-use std::io::{Read, Write};
 
 /// error types
 #[derive(Debug)]
@@ -12,6 +8,27 @@ pub enum GameError {
     LoadError(String),
     NoSuitableElfError(String),
     NoSuitableBuildingError(String),
+    NoSuchGroupError(String),
+    NoSuchTaskError(String),
+    NoSuchOrderError(String),
+    NoSuchRecipeError(String),
+    NoSuchProjectError(String),
+    NoSuchEncounterError(String),
+
+    /// the item can't be equipped (it isn't equipment, or the wearer isn't agile enough)
+    EquipmentError(String),
+
+    /// the stronghold has no room (see StrongholdStats.size), or a stat is already at its highest level
+    CapacityError(String),
+
+    /// the building already has a project underway
+    ProjectExistsError(String),
+
+    /// what the stockpile is short of to pay a cost
+    ShortfallError(Cost),
+    SlotNotFoundError(String),
+    SlotExistsError(String),
+    LoopError(String),
     IoError(std::io::Error),
     ParseError(std::num::ParseIntError),
     Utf8Error(std::str::Utf8Error),
@@ -43,110 +60,147 @@ impl From<serde_json::Error> for GameError {
 
 
 pub trait GameOptions {
-    fn new(name: Option<String>, username: Option<String>) -> Self;
+    fn new(name: Option<String>, username: Option<String>, seed: Option<u64>) -> Self;
     fn save(&self) -> Result<(), GameError>;
     fn load(name: String) -> Result<Self, GameError> where Self: Sized;
     fn tick(&mut self) -> Result<(), GameError> ;
 }
 
 impl GameOptions for World {
-    fn new(name: Option<String>, username: Option<String>) -> Self {
-        let mut elves = Vec::new();
-        
-        // generate initial 11 elves
-        
-        elves.push(Elf::new(None, None, Some(vec![Role::Leader, Role::Elder])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Trader, Role::Elder, Role::Miner])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Trader, Role::Warrior])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Warrior, Role::Elder])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Warrior, Role::Miner])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Warrior])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Scientist, Role::Elder])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Farmer, Role::Elder])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Cook])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Farmer])));
-        elves.push(Elf::new(None, None, Some(vec![Role::Miner])));
-
-
+    fn new(name: Option<String>, username: Option<String>, seed: Option<u64>) -> Self {
+        // a random seed is only picked once - everything after that is reproducible from it
+        let seed = seed.unwrap_or_else(rand::random);
+        let mut rng = GameRng::new(seed);
 
+        // generate initial 11 elves
+        let elves = vec![
+            Elf::new(&mut rng, None, None, Some(vec![Role::Leader, Role::Elder])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Trader, Role::Elder, Role::Miner])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Trader, Role::Warrior])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Warrior, Role::Elder])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Warrior, Role::Miner])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Warrior])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Scientist, Role::Elder])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Farmer, Role::Elder])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Cook])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Farmer])),
+            Elf::new(&mut rng, None, None, Some(vec![Role::Miner])),
+        ];
 
-        // generate initial 3 buildings
+        // generate initial 4 buildings
         let buildings = vec![
             Building {
                 id: 0,
                 building_type: BuildingType::MeetingHall,
-                level: 1
+                level: 1,
+                health: max_health(1),
             },
             Building {
                 id: 1,
                 building_type: BuildingType::Barracks,
-                level: 1
+                level: 1,
+                health: max_health(1),
             },
             Building {
                 id: 2,
                 building_type: BuildingType::Farm,
-                level: 1
+                level: 1,
+                health: max_health(1),
+            },
+            Building {
+                id: 3,
+                building_type: BuildingType::Dormitory,
+                level: 3,
+                health: max_health(3),
             }
         ];
 
+        let next_building_id = buildings.len() as u32;
+
         // generate a stockpile: 110 food, 550 water
-        let mut stockpile = vec![];
+        let mut stockpile = Inventory::new();
 
-        for _ in 0..110 {
-            stockpile.push(Object {
+        stockpile.put(
+            Object {
                 object_type: ObjectType::Food {
                     name: "Unidentified Meat".to_string(),
                     description: "A piece of meat from an unknown animal".to_string(),
                     hunger_power: 3.0,
                 },
                 resource_type: ResourceType::Animal,
-            });
-        }
+            },
+            110,
+        );
 
-        for _ in 0..550 {
-            stockpile.push(Object {
+        stockpile.put(
+            Object {
                 object_type: ObjectType::Water,
                 resource_type: ResourceType::Glass, // glass bottle
-            });
-        }
+            },
+            550,
+        );
 
-        let stronghold = Stronghold {
+        let mut stronghold = Stronghold {
             name: format!("First Stronghold of {} Colony", name.clone().unwrap_or_else(|| "Earth".to_string())),
-            elves,
+            elves: Vec::new(),
             buildings,
             task_queue: Vec::new(),
+            active_tasks: Vec::new(),
+            task_log: Vec::new(),
+            next_task_id: 0,
+            projects: Vec::new(),
+            next_building_id,
             stockpile,
+            next_elf_id: 0,
+            work_groups: Vec::new(),
+            next_work_group_id: 0,
+            standing_orders: Vec::new(),
+            next_order_id: 0,
+            orders_paused: false,
+            stats: StrongholdStats {
+                size: 1,
+                movement: 1,
+                fortification: 1,
+            },
         };
 
+        for elf in elves {
+            stronghold.add_elf(elf);
+        }
+
         let colony = Colony {
             name: name.clone().unwrap_or_else(|| "Earth".to_string()),
             strongholds: vec![stronghold],
             leader: username.unwrap_or_else(|| "Player".to_string()),
+            travellers: Vec::new(),
         };
 
+        // goblins have settled nearby
+        let goblins = GoblinStronghold::new(&mut rng, "Goblin Warren".to_string(), colony.name.clone(), GOBLIN_POPULATION, 0);
+
         World {
             name: name.unwrap_or_else(|| "World".to_string()),
             colonies: vec![colony],
             tick: 0,
-        } 
+            seed,
+            rng,
+            events: Vec::new(),
+            encounters: Vec::new(),
+            next_encounter_id: 0,
+            goblin_strongholds: vec![goblins],
+        }
     }
 
+    /// save to the slot named after the world in the default save directory
     fn save(&self) -> Result<(), GameError> {
-        let json = serde_json::to_string(&self)?;
-        let mut file = File::create("world.json")?;
-        file.write_all(json.as_bytes())?;
+        SaveDirectory::default().save(&self.name, self)?;
 
         Ok(())
     }
 
+    /// load the named slot from the default save directory
     fn load(name: String) -> Result<Self, GameError> {
-        let mut file = File::open("world.json")?;
-        let mut buffer = String::new();
-        file.read_to_string(&mut buffer)?;
-
-        let world: World = serde_json::from_str(&buffer)?;
-
-        Ok(world)
+        SaveDirectory::default().load(&name)
     }
 
     fn tick(&mut self) -> Result<(), GameError> {
@@ -154,24 +208,49 @@ impl GameOptions for World {
 
         // check all elves for task completion
         for colony in &mut self.colonies {
+            let colony_leader = leader_influence(colony);
+
             for stronghold in &mut colony.strongholds {
-                stronghold.check_tasks_complete(self.tick)?;
-
-                // now check if there are any tasks we can now do in the task queue
-                // for every task in the queue, try to call assign_task, and if returned Ok(true), remove the task from the queue
-                let mut i = 0;
-                while i < stronghold.task_queue.len() {
-                    let task = stronghold.task_queue[i].clone();
-                    if stronghold.new_task(task)? {
-                        stronghold.task_queue.remove(i);
-                    } else {
-                        i += 1;
-                    }
-                }
+                // elves get hungry, thirsty and tired, and help themselves to the stockpile
+                stronghold.tick_needs(self.tick, &mut self.rng)?;
+                stronghold.update_happiness(colony_leader)?;
+
+                // critical needs hurt, and the dead are taken off the roster
+                stronghold.tick_health(self.tick)?;
+                self.events.extend(stronghold.bury_dead(self.tick)?);
+
+                // fires and storms now and then damage buildings
+                self.events.extend(stronghold.disasters(self.tick, &mut self.rng)?);
+
+                // finished tasks hand over what they produced to the stockpile
+                stronghold.check_tasks_complete()?;
+
+                // standing orders that are due put in fresh tasks
+                stronghold.issue_orders()?;
+
+                // now check if there are any tasks we can now do in the task queue, most important first
+                stronghold.schedule_tasks()?;
+
+                // newly assigned tasks use up their reserved objects as they begin
+                stronghold.start_tasks(self.tick)?;
 
             }
+
+            // a stronghold that has lost every building is abandoned
+            self.events.extend(disband_fallen(colony, self.tick)?);
+
+            // elves on the road between strongholds arrive
+            arrivals(colony, self.tick);
         }
 
+        // every fight underway trades one exchange
+        let events = self.tick_encounters()?;
+        self.events.extend(events);
+
+        // goblins grow, come home from raids and set out on new ones
+        let events = self.tick_goblins()?;
+        self.events.extend(events);
+
         self.tick += 1;
 
         Ok(())
@@ -202,30 +281,14 @@ impl Train for Stronghold {
             return Err(GameError::NoSuitableElfError("Elf is already occupied".to_string()));
         }
 
-        // determine how many resources we need to train the elf (specified by profession_to_resource)
-        let required_resources = profession_to_resource(role.clone());
-
-        // check if we have enough resources
+        // training costs one more of the profession's resource (see profession_to_resource) than the elf's current level
         let num_required = match elf.roles.iter().find(|r| r.role == role) {
-            Some(role) => role.ability as i32 + 1,
+            Some(role) => role.ability as u32 + 1,
             None => 1,
         };
 
-        // now check if we have enough resources
-        let mut num_resources = 0;
-
-        for object in &self.stockpile {
-            if object.resource_type == required_resources {
-                num_resources += 1;
-            }
-        }
-
-        if num_resources < num_required {
-            return Err(GameError::NoSuitableBuildingError("Not enough resources".to_string()));
-        }
-
-        // remove the resources
-        self.stockpile.retain(|object| object.resource_type != required_resources);
+        // take exactly that many, if we have them
+        self.stockpile.pay(&Cost::resource(profession_to_resource(role.clone()), num_required))?;
 
         // now train the elf
         let role_ability = elf.roles.iter_mut().find(|r| r.role == role);
@@ -242,6 +305,7 @@ impl Train for Stronghold {
             elf.roles.push(RoleAbility {
                 role,
                 ability: AttributeLevel::Terrible,
+                experience: 0,
             });
         }
 
@@ -254,75 +318,117 @@ pub trait UpgradeBuilding {
 }
 
 impl UpgradeBuilding for Stronghold {
+    /// start upgrading every building of the given type - if there are none, start putting one up at level 1. builders do the work, and levels only go up once it is done
     fn upgrade_building(&mut self, building: BuildingType) -> Result<(), GameError> {
-        // upgrade all buildings of the given type - if there are none, create one at level 1
+        let mut projects = self
+            .buildings
+            .iter()
+            .filter(|b| b.building_type == building)
+            .map(|b| (building, Some(b.id)))
+            .collect::<Vec<(BuildingType, Option<u32>)>>();
+
+        if projects.is_empty() {
+            projects.push((building, None));
+        }
 
-        // for each building, consume resources of the level of the building + 1
-        // if we don't have enough resources, return an error
+        self.start_projects(projects)?;
 
-        let mut found = false;
+        Ok(())
+    }
+}
 
-        // first, upgrade any and all existing buildings
-        for checked_building in &mut self.buildings {
-            if checked_building.building_type == building {
-                found = true;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    fn wood(name: &str) -> Object {
+        Object {
+            resource_type: ResourceType::Wood,
+            object_type: ObjectType::RawMaterial {
+                name: name.to_string(),
+                description: "Test wood".to_string(),
+            },
+        }
+    }
 
-                // first, check if we have enough resources
-                let num_required = checked_building.level + 1;
+    fn stacks(stockpile: &Inventory) -> Vec<Stack> {
+        stockpile.stacks().filter(|stack| stack.count > 0).cloned().collect()
+    }
 
-                let mut num_resources = 0;
+    /// a stronghold with an idle average warrior (training takes wood) and a stockpile of mixed wood, meat and water
+    fn barracks() -> Stronghold {
+        let mut world = World::new(None, None, Some(7));
+        let mut stronghold = world.colonies.remove(0).strongholds.remove(0);
 
-                for object in &self.stockpile {
-                    if object.resource_type == building_to_resource(building.clone()) {
-                        num_resources += 1;
-                    }
-                }
+        let elf = &mut stronghold.elves[0];
+        elf.task = None;
+        elf.roles = vec![RoleAbility {
+            role: Role::Warrior,
+            ability: AttributeLevel::Average,
+            experience: 0,
+        }];
 
-                if num_resources < num_required {
-                    return Err(GameError::NoSuitableBuildingError("Not enough resources".to_string()));
-                }
+        stronghold.stockpile.put(wood("Oak"), 2);
+        stronghold.stockpile.put(wood("Birch"), 2);
 
-                // remove num_required resources
-                for _ in 0..num_required {
-                    self.stockpile.remove(
-                        self.stockpile.iter().position(|object| object.resource_type == building_to_resource(building.clone())).unwrap());
-                }
+        stronghold
+    }
 
-                checked_building.level += 1;
-            }
-        }
+    #[test]
+    fn training_takes_exactly_one_more_than_the_ability() {
+        let mut stronghold = barracks();
+        let others = stronghold.stockpile.total() - stronghold.stockpile.count_resource(ResourceType::Wood);
 
-        if !found {
-            // create a new building
-            let num_required = 1;
+        stronghold.train(0, Role::Warrior).unwrap();
 
-            let mut num_resources = 0;
+        assert_eq!(stronghold.elves[0].roles[0].ability, AttributeLevel::Good);
+        assert_eq!(stronghold.stockpile.count_resource(ResourceType::Wood), 4 - (AttributeLevel::Average as u32 + 1));
+        assert_eq!(stronghold.stockpile.total() - stronghold.stockpile.count_resource(ResourceType::Wood), others);
+    }
 
+    #[test]
+    fn failed_training_takes_nothing_and_names_the_shortfall() {
+        let mut stronghold = barracks();
+        stronghold.elves[0].roles[0].ability = AttributeLevel::Good;
 
-            for object in &self.stockpile {
-                if object.resource_type == building_to_resource(building.clone()) {
-                    num_resources += 1;
-                }
-            }
+        let before = stacks(&stronghold.stockpile);
 
-            if num_resources < num_required {
-                return Err(GameError::NoSuitableBuildingError("Not enough resources".to_string()));
-            }
+        match stronghold.train(0, Role::Warrior) {
+            Err(GameError::ShortfallError(shortfall)) => assert_eq!(shortfall, Cost::resource(ResourceType::Wood, 1)),
+            other => panic!("expected a shortfall, got {:?}", other),
+        }
 
-            // remove num_required resources
-            for _ in 0..num_required {
-                self.stockpile.remove(
-                    self.stockpile.iter().position(|object| object.resource_type == building_to_resource(building.clone())).unwrap());
-            }
+        assert_eq!(stacks(&stronghold.stockpile), before);
+        assert_eq!(stronghold.elves[0].roles[0].ability, AttributeLevel::Good);
+    }
 
-            self.buildings.push(Building {
-                id: self.buildings.len() as u32,
-                building_type: building,
-                level: 1,
-            });
+    #[test]
+    fn shortfall_lists_every_missing_resource() {
+        let stronghold = barracks();
+        let cost = Cost::resource(ResourceType::Wood, 10)
+            .and_resource(ResourceType::Stone, 3)
+            .and_resource(ResourceType::Animal, 1)
+            .and_objects(&[Stack {
+                object: wood("Oak"),
+                count: 3,
+            }]);
+
+        let mut stockpile = stronghold.stockpile.clone();
+        let before = stacks(&stockpile);
+
+        match stockpile.pay(&cost) {
+            Err(GameError::ShortfallError(shortfall)) => assert_eq!(
+                shortfall,
+                Cost::objects(&[Stack {
+                    object: wood("Oak"),
+                    count: 1,
+                }])
+                .and_resource(ResourceType::Wood, 8)
+                .and_resource(ResourceType::Stone, 3)
+            ),
+            other => panic!("expected a shortfall, got {:?}", other),
         }
 
-        Ok(())
+        assert_eq!(stacks(&stockpile), before);
     }
-}
\ No newline at end of file
+}
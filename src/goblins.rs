@@ -0,0 +1,243 @@
+use crate::{battle::*, defs::*, game::*, impls::*, needs::*};
+use rand::Rng;
+
+/// goblins a new goblin stronghold starts with
+pub const GOBLIN_POPULATION: usize = 4;
+
+/// most goblins a goblin stronghold can hold at home
+pub const MAX_GOBLINS: usize = 12;
+
+/// how often goblins scavenge, heal, breed and think about raiding (an in game hour)
+pub const GOBLIN_WORK_TICKS: u64 = TICKS_PER_HOUR;
+
+/// health each goblin at home gets back every GOBLIN_WORK_TICKS
+pub const GOBLIN_HEALING: i32 = 10;
+
+/// food (animal) it takes to raise another goblin
+pub const BREED_COST: u32 = 20;
+
+/// ticks a goblin stronghold waits after one raid before setting out on another (a day)
+pub const RAID_COOLDOWN: u64 = TICKS_PER_HOUR * 24;
+
+/// goblins with less health than this stay home from raids
+pub const RAID_HEALTH: i32 = 50;
+
+/// goblins only raid when their warband is at least this many times as strong as the target's defence
+pub const RAID_ODDS: f32 = 2.0;
+
+/// what goblins bring back from scavenging a resource
+pub fn scavenged(resource_type: ResourceType) -> Object {
+    Object {
+        resource_type,
+        object_type: ObjectType::RawMaterial {
+            name: format!("Scavenged {:?}", resource_type),
+            description: "Whatever a goblin could carry off".to_string(),
+        },
+    }
+}
+
+impl GoblinStronghold {
+    /// a goblin stronghold bordering a colony, with a population of new goblins
+    pub fn new<R: Rng + ?Sized>(rng: &mut R, name: String, colony: String, population: usize, tick: u64) -> Self {
+        GoblinStronghold {
+            name,
+            colony,
+            goblins: (0..population).map(|_| Goblin::new(rng, None, None, None)).collect(),
+            stockpile: Inventory::new(),
+            raiders: Vec::new(),
+            raid: None,
+            last_raid: tick,
+        }
+    }
+
+    /// every goblin at home scavenges a resource and heals a little, then the food goes on raising more goblins while there is room
+    pub fn grow<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        use ResourceType::*;
+
+        for goblin in &mut self.goblins {
+            let resource = [Wood, Stone, Iron, Animal][rng.gen_range(0..4)];
+            self.stockpile.put(scavenged(resource), 1);
+
+            goblin.health = (goblin.health + GOBLIN_HEALING).min(100);
+        }
+
+        while self.goblins.len() + self.raiders.len() < MAX_GOBLINS {
+            if self.stockpile.pay(&Cost::resource(Animal, BREED_COST)).is_err() {
+                break;
+            }
+
+            self.goblins.push(Goblin::new(rng, None, None, None));
+        }
+    }
+
+    /// goblins fit to go raiding
+    pub fn warband(&self) -> Vec<Combatant> {
+        self.goblins
+            .iter()
+            .filter(|goblin| goblin.health >= RAID_HEALTH)
+            .map(|goblin| Combatant::goblin(goblin, Side::Attackers))
+            .collect()
+    }
+}
+
+pub trait Goblins {
+    fn goblin_stronghold(&self, name: &str) -> Option<&GoblinStronghold>;
+    fn found_goblin_stronghold(&mut self, colony: &str, name: String) -> Result<(), GameError>;
+    fn tick_goblins(&mut self) -> Result<Vec<GameEvent>, GameError>;
+}
+
+impl Goblins for World {
+    fn goblin_stronghold(&self, name: &str) -> Option<&GoblinStronghold> {
+        self.goblin_strongholds.iter().find(|goblins| goblins.name == name)
+    }
+
+    /// settle a new goblin stronghold by a colony (worlds from before goblins have none until one is founded). it rests a day before its first raid
+    fn found_goblin_stronghold(&mut self, colony: &str, name: String) -> Result<(), GameError> {
+        if !self.colonies.iter().any(|c| c.name == colony) {
+            return Err(GameError::NoSuitableBuildingError(format!("No colony {}", colony)));
+        }
+
+        if self.goblin_stronghold(&name).is_some() {
+            return Err(GameError::NoSuitableBuildingError(format!("There is already a goblin stronghold called {}", name)));
+        }
+
+        let goblins = GoblinStronghold::new(&mut self.rng, name, colony.to_string(), GOBLIN_POPULATION, self.tick);
+        self.goblin_strongholds.push(goblins);
+
+        Ok(())
+    }
+
+    /// bring raiders home from finished raids with their loot, then every GOBLIN_WORK_TICKS let each goblin stronghold grow and,
+    /// once it has rested from its last raid, raid the weakest stronghold of its colony if its warband is strong enough to take it
+    fn tick_goblins(&mut self) -> Result<Vec<GameEvent>, GameError> {
+        let mut events = Vec::new();
+
+        for i in 0..self.goblin_strongholds.len() {
+            if let Some(raid) = self.goblin_strongholds[i].raid {
+                // a raid that has been forgotten is over too, and its raiders just come home
+                let (over, combatants, loot) = match self.encounters.iter().find(|encounter| encounter.id == raid) {
+                    Some(encounter) => {
+                        let report = encounter.battle.as_ref().and_then(|battle| battle.report.as_ref());
+
+                        (
+                            report.is_some(),
+                            encounter.combatants.clone(),
+                            report.map(|report| report.loot.clone()).unwrap_or_default(),
+                        )
+                    }
+                    None => (true, Vec::new(), Vec::new()),
+                };
+
+                if over {
+                    let goblins = &mut self.goblin_strongholds[i];
+                    goblins.stockpile.put_all(&loot);
+
+                    // raiders fought first in the battle, in the order they set out
+                    for (n, mut raider) in std::mem::take(&mut goblins.raiders).into_iter().enumerate() {
+                        if let Some(combatant) = combatants.get(n) {
+                            raider.health = combatant.health;
+                        }
+
+                        if raider.health > 0 {
+                            goblins.goblins.push(raider);
+                        }
+                    }
+
+                    goblins.raid = None;
+                }
+            }
+
+            if !self.tick.is_multiple_of(GOBLIN_WORK_TICKS) {
+                continue;
+            }
+
+            self.goblin_strongholds[i].grow(&mut self.rng);
+
+            let goblins = &self.goblin_strongholds[i];
+
+            if goblins.raid.is_some() || self.tick < goblins.last_raid + RAID_COOLDOWN {
+                continue;
+            }
+
+            let warband = goblins.warband();
+
+            if warband.is_empty() {
+                continue;
+            }
+
+            let target = self
+                .colonies
+                .iter()
+                .filter(|colony| colony.name == goblins.colony)
+                .flat_map(|colony| colony.strongholds.iter())
+                .map(|stronghold| {
+                    let (cover, volley) = fortified(stronghold);
+
+                    (stronghold.name.clone(), fighting_strength(&defenders(stronghold), cover, volley))
+                })
+                .min_by_key(|(_, strength)| *strength);
+
+            let Some((target, defence)) = target else {
+                continue;
+            };
+
+            if (fighting_strength(&warband, 0, 0) as f32) < defence as f32 * RAID_ODDS {
+                continue;
+            }
+
+            let name = goblins.name.clone();
+            let raiders = warband.len() as u32;
+            let encounter = self.start_battle(&target, warband, Some(name.clone()))?;
+
+            let goblins = &mut self.goblin_strongholds[i];
+            let (raiding, home): (Vec<Goblin>, Vec<Goblin>) =
+                std::mem::take(&mut goblins.goblins).into_iter().partition(|goblin| goblin.health >= RAID_HEALTH);
+
+            goblins.goblins = home;
+            goblins.raiders = raiding;
+            goblins.raid = Some(encounter);
+            goblins.last_raid = self.tick;
+
+            events.push(GameEvent::Raid {
+                tick: self.tick,
+                goblins: name,
+                stronghold: target,
+                raiders,
+                encounter,
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn goblins_can_settle_by_a_migrated_world() {
+        let fixtures = SaveDirectory::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/saves"));
+        let mut world = fixtures.load("v21").unwrap();
+        assert!(world.goblin_strongholds.is_empty());
+
+        let colony = world.colonies[0].name.clone();
+        world.found_goblin_stronghold(&colony, "Goblin Warren".to_string()).unwrap();
+
+        let goblins = world.goblin_stronghold("Goblin Warren").unwrap();
+        assert_eq!(goblins.colony, colony);
+        assert_eq!(goblins.goblins.len(), GOBLIN_POPULATION);
+        assert_eq!(goblins.last_raid, world.tick);
+
+        assert!(world.found_goblin_stronghold(&colony, "Goblin Warren".to_string()).is_err());
+        assert!(world.found_goblin_stronghold("Nowhere", "Goblin Den".to_string()).is_err());
+
+        for _ in 0..GOBLIN_WORK_TICKS {
+            world.tick().unwrap();
+        }
+
+        assert!(world.goblin_stronghold("Goblin Warren").unwrap().stockpile.total() > 0);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{defs::*, game::*};
+
+pub trait Roster {
+    fn add_elf(&mut self, elf: Elf) -> u32;
+    fn elf_index(&self, id: u32) -> Option<usize>;
+}
+
+pub trait Groups {
+    fn create_work_group(&mut self, name: String) -> u32;
+    fn rename_work_group(&mut self, group: u32, name: String) -> Result<(), GameError>;
+    fn delete_work_group(&mut self, group: u32) -> Result<WorkGroup, GameError>;
+    fn add_to_work_group(&mut self, group: u32, elf: u32) -> Result<(), GameError>;
+    fn remove_from_work_group(&mut self, group: u32, elf: u32) -> Result<(), GameError>;
+    fn work_group(&self, group: u32) -> Result<&WorkGroup, GameError>;
+    fn profession_group(&self, role: &Role) -> Vec<u32>;
+    fn profession_groups(&self) -> Vec<(Role, Vec<u32>)>;
+    fn members(&self, assignee: &Assignee) -> Result<Vec<u32>, GameError>;
+}
+
+impl Roster for Stronghold {
+    /// add an elf to the stronghold, giving it a fresh ID
+    fn add_elf(&mut self, mut elf: Elf) -> u32 {
+        elf.id = self.next_elf_id;
+        self.next_elf_id += 1;
+
+        let id = elf.id;
+        self.elves.push(elf);
+
+        id
+    }
+
+    /// position of an elf in the roster
+    fn elf_index(&self, id: u32) -> Option<usize> {
+        self.elves.iter().position(|elf| elf.id == id)
+    }
+}
+
+impl Groups for Stronghold {
+    fn create_work_group(&mut self, name: String) -> u32 {
+        let id = self.next_work_group_id;
+        self.next_work_group_id += 1;
+
+        self.work_groups.push(WorkGroup {
+            id,
+            name,
+            members: Vec::new(),
+        });
+
+        id
+    }
+
+    fn rename_work_group(&mut self, group: u32, name: String) -> Result<(), GameError> {
+        work_group_mut(self, group)?.name = name;
+
+        Ok(())
+    }
+
+    /// delete a work group. tasks queued, running or ordered for it are opened up to anyone
+    fn delete_work_group(&mut self, group: u32) -> Result<WorkGroup, GameError> {
+        let position = self
+            .work_groups
+            .iter()
+            .position(|g| g.id == group)
+            .ok_or_else(|| GameError::NoSuchGroupError(format!("No work group {}", group)))?;
+
+        // tasks and orders for the group fall to anyone, wherever they are
+        let tasks = self
+            .task_queue
+            .iter_mut()
+            .chain(self.active_tasks.iter_mut())
+            .chain(self.standing_orders.iter_mut().map(|order| &mut order.task));
+
+        for task in tasks {
+            if task.assignee == Assignee::WorkGroup(group) {
+                task.assignee = Assignee::Anyone;
+            }
+        }
+
+        Ok(self.work_groups.remove(position))
+    }
+
+    fn add_to_work_group(&mut self, group: u32, elf: u32) -> Result<(), GameError> {
+        if self.elf_index(elf).is_none() {
+            return Err(GameError::NoSuitableElfError(format!("No elf {}", elf)));
+        }
+
+        let group = work_group_mut(self, group)?;
+
+        if !group.members.contains(&elf) {
+            group.members.push(elf);
+        }
+
+        Ok(())
+    }
+
+    fn remove_from_work_group(&mut self, group: u32, elf: u32) -> Result<(), GameError> {
+        work_group_mut(self, group)?.members.retain(|member| *member != elf);
+
+        Ok(())
+    }
+
+    fn work_group(&self, group: u32) -> Result<&WorkGroup, GameError> {
+        self.work_groups
+            .iter()
+            .find(|g| g.id == group)
+            .ok_or_else(|| GameError::NoSuchGroupError(format!("No work group {}", group)))
+    }
+
+    /// IDs of every elf with a profession
+    fn profession_group(&self, role: &Role) -> Vec<u32> {
+        self.elves
+            .iter()
+            .filter(|elf| elf.roles.iter().any(|r| r.role == *role))
+            .map(|elf| elf.id)
+            .collect()
+    }
+
+    /// every profession held in the stronghold and who holds it, in roster order
+    fn profession_groups(&self) -> Vec<(Role, Vec<u32>)> {
+        let mut groups: Vec<(Role, Vec<u32>)> = Vec::new();
+
+        for elf in &self.elves {
+            for role_ability in &elf.roles {
+                match groups.iter_mut().find(|(role, _)| *role == role_ability.role) {
+                    Some((_, members)) => members.push(elf.id),
+                    None => groups.push((role_ability.role.clone(), vec![elf.id])),
+                }
+            }
+        }
+
+        groups
+    }
+
+    /// IDs of every elf a task with this assignee could go to
+    fn members(&self, assignee: &Assignee) -> Result<Vec<u32>, GameError> {
+        match assignee {
+            Assignee::Anyone => Ok(self.elves.iter().map(|elf| elf.id).collect()),
+            Assignee::Elf(id) => Ok(self.elf_index(*id).map(|_| vec![*id]).unwrap_or_default()),
+            Assignee::WorkGroup(group) => Ok(self.work_group(*group)?.members.clone()),
+            Assignee::Profession(role) => Ok(self.profession_group(role)),
+        }
+    }
+}
+
+fn work_group_mut(stronghold: &mut Stronghold, group: u32) -> Result<&mut WorkGroup, GameError> {
+    stronghold
+        .work_groups
+        .iter_mut()
+        .find(|g| g.id == group)
+        .ok_or_else(|| GameError::NoSuchGroupError(format!("No work group {}", group)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orders::*;
+
+    #[test]
+    fn deleting_a_group_frees_its_queued_running_and_ordered_tasks() {
+        let mut world = World::new(None, None, Some(1));
+        let stronghold = &mut world.colonies[0].strongholds[0];
+        let group = stronghold.create_work_group("Night shift".to_string());
+
+        let mut task = Task::new("Guard".to_string(), 10, BuildingType::MeetingHall, Vec::new());
+        task.assignee = Assignee::WorkGroup(group);
+
+        stronghold.task_queue.push(task.clone());
+        stronghold.active_tasks.push(task.clone());
+        stronghold.add_order(task, OrderKind::Repeat);
+
+        stronghold.delete_work_group(group).unwrap();
+
+        assert!(stronghold
+            .task_queue
+            .iter()
+            .chain(stronghold.active_tasks.iter())
+            .chain(stronghold.standing_orders.iter().map(|order| &order.task))
+            .all(|task| task.assignee == Assignee::Anyone));
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{defs::*, game::*, needs::*};
+
+/// beds each level of dormitory holds
+pub const BEDS_PER_LEVEL: u32 = 4;
+
+/// holding elder or leader for this long makes losing it hurt
+pub const LONG_TENURE_TICKS: u64 = TICKS_PER_HOUR * 24 * 7;
+
+/// elves this old expect to be elders
+pub const ELDER_AGE: f32 = 70.0;
+
+/// why an elf is (un)happy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HappinessSource {
+    Hunger,
+    Thirst,
+    Sleep,
+
+    /// working a task, or idle
+    Job,
+
+    /// having a bed, or not
+    Bed,
+
+    /// patient elves shrug off part of their displeasure, impatient ones make it worse
+    Patience,
+    Elder,
+    Leader,
+
+    /// traits of the colony's leader
+    ColonyLeader,
+
+    /// having been an elder for a long time, but no longer
+    FormerElder,
+
+    /// having been the leader for a long time, but no longer
+    FormerLeader,
+
+    /// being old without being an elder
+    NotElder,
+
+    /// an alchemist working in the same building as a scientist
+    ScientistNearby,
+}
+
+/// one contribution to an elf's happiness
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HappinessModifier {
+    pub source: HappinessSource,
+    pub value: i32,
+}
+
+/// every modifier on an elf's happiness, and the level they add up to (starting from Content)
+#[derive(Debug, Clone, PartialEq)]
+pub struct HappinessBreakdown {
+    pub modifiers: Vec<HappinessModifier>,
+    pub level: HappinessLevel,
+}
+
+impl HappinessLevel {
+    /// level from its numeric value, clamped to Depressed..=Ecstatic
+    pub fn from_value(value: i32) -> HappinessLevel {
+        match value {
+            i32::MIN..=0 => HappinessLevel::Depressed,
+            1 => HappinessLevel::Miserable,
+            2 => HappinessLevel::Unhappy,
+            3 => HappinessLevel::Content,
+            4 => HappinessLevel::Happy,
+            _ => HappinessLevel::Ecstatic,
+        }
+    }
+}
+
+pub trait Happiness {
+    fn beds(&self) -> u32;
+    fn happiness_breakdown(&self, elf_index: usize, colony_leader: i32) -> HappinessBreakdown;
+    fn update_happiness(&mut self, colony_leader: i32) -> Result<(), GameError>;
+}
+
+/// how a need level feels
+fn need_modifier(level: AttributeLevel) -> i32 {
+    match level {
+        AttributeLevel::Excellent | AttributeLevel::Good => 1,
+        AttributeLevel::Average => 0,
+        AttributeLevel::Poor => -1,
+        AttributeLevel::Terrible => -2,
+    }
+}
+
+fn has_role(elf: &Elf, role: &Role) -> bool {
+    elf.roles.iter().any(|r| r.role == *role)
+}
+
+/// how long an elf has held a role, in ticks
+fn tenure(elf: &Elf, role: &Role) -> u64 {
+    elf.tenure.iter().find(|t| t.role == *role).map(|t| t.ticks).unwrap_or(0)
+}
+
+/// effect of a colony's leader on every elf in it: good traits lift the mood, bad ones sour it
+pub fn leader_influence(colony: &Colony) -> i32 {
+    let leader = colony
+        .strongholds
+        .iter()
+        .flat_map(|stronghold| stronghold.elves.iter())
+        .find(|elf| has_role(elf, &Role::Leader));
+
+    let Some(leader) = leader else {
+        return 0;
+    };
+
+    let traits = [leader.patience, leader.intelligence, leader.obedience, leader.loyalty];
+    let total = traits.iter().map(|level| *level as i32).sum::<i32>();
+
+    // above average traits all round is +1, below is -1
+    match total {
+        t if t >= 4 * AttributeLevel::Good as i32 => 1,
+        t if t <= 4 * AttributeLevel::Poor as i32 => -1,
+        _ => 0,
+    }
+}
+
+impl Happiness for Stronghold {
+    /// beds in the stronghold's dormitories. the first elves on the roster get them
+    fn beds(&self) -> u32 {
+        self.buildings
+            .iter()
+            .filter(|building| building.building_type == BuildingType::Dormitory)
+            .map(|building| building.level * BEDS_PER_LEVEL)
+            .sum()
+    }
+
+    /// work out why an elf feels the way it does
+    fn happiness_breakdown(&self, elf_index: usize, colony_leader: i32) -> HappinessBreakdown {
+        let elf = &self.elves[elf_index];
+        let mut modifiers = Vec::new();
+
+        let mut add = |source, value| {
+            if value != 0 {
+                modifiers.push(HappinessModifier { source, value });
+            }
+        };
+
+        add(HappinessSource::Hunger, need_modifier(elf.hunger));
+        add(HappinessSource::Thirst, need_modifier(elf.thirst));
+        add(HappinessSource::Sleep, need_modifier(elf.sleep));
+        add(HappinessSource::Job, if elf.task.is_some() { 1 } else { -1 });
+        add(HappinessSource::Bed, if (elf_index as u32) < self.beds() { 1 } else { -1 });
+
+        let elder = has_role(elf, &Role::Elder);
+        let leader = has_role(elf, &Role::Leader);
+
+        add(HappinessSource::Elder, elder as i32);
+        add(HappinessSource::Leader, leader as i32);
+        add(HappinessSource::ColonyLeader, colony_leader);
+
+        if !elder && tenure(elf, &Role::Elder) >= LONG_TENURE_TICKS {
+            add(HappinessSource::FormerElder, -1);
+        }
+
+        if !leader && tenure(elf, &Role::Leader) >= LONG_TENURE_TICKS {
+            add(HappinessSource::FormerLeader, -1);
+        }
+
+        if !elder && elf.age >= ELDER_AGE {
+            add(HappinessSource::NotElder, -1);
+        }
+
+        // scientists look down on alchemists sharing their building
+        if has_role(elf, &Role::Alchemist) {
+            let building = |elf: &Elf| {
+                self.active_tasks
+                    .iter()
+                    .find(|task| Some(task.id) == elf.task)
+                    .and_then(|task| task.building)
+            };
+
+            if let Some(workplace) = building(elf) {
+                let scientist_nearby = self.elves.iter().enumerate().any(|(i, other)| {
+                    i != elf_index && has_role(other, &Role::Scientist) && building(other) == Some(workplace)
+                });
+
+                if scientist_nearby {
+                    add(HappinessSource::ScientistNearby, -1);
+                }
+            }
+        }
+
+        // patience scales displeasure: average patience leaves it be, excellent cuts it to a third, terrible makes it 5/3
+        let displeasure = modifiers.iter().map(|m| m.value).filter(|v| *v < 0).sum::<i32>();
+        let damped = displeasure * (6 - elf.patience as i32) / 3;
+
+        if damped != displeasure {
+            modifiers.push(HappinessModifier {
+                source: HappinessSource::Patience,
+                value: damped - displeasure,
+            });
+        }
+
+        let total = modifiers.iter().map(|m| m.value).sum::<i32>();
+
+        HappinessBreakdown {
+            modifiers,
+            level: HappinessLevel::from_value(HappinessLevel::Content as i32 + total),
+        }
+    }
+
+    /// track status tenure and recompute every elf's happiness
+    fn update_happiness(&mut self, colony_leader: i32) -> Result<(), GameError> {
+        for elf in &mut self.elves {
+            for role in [Role::Elder, Role::Leader] {
+                if !has_role(elf, &role) {
+                    continue;
+                }
+
+                match elf.tenure.iter_mut().find(|t| t.role == role) {
+                    Some(tenure) => tenure.ticks += 1,
+                    None => elf.tenure.push(RoleTenure { role, ticks: 1 }),
+                }
+            }
+        }
+
+        for elf_index in 0..self.elves.len() {
+            self.elves[elf_index].happiness = self.happiness_breakdown(elf_index, colony_leader).level;
+        }
+
+        Ok(())
+    }
+}
@@ -1,26 +1,28 @@
+use crate::construction::*;
 use crate::defs::*;
+use crate::equipment::*;
 use crate::game::*;
+use crate::groups::*;
+use crate::skills::*;
 
 // random number generator
-use rand::{random, Rng};
+use rand::Rng;
 
 pub trait NewEntity {
-    fn new(age: Option<f32>, surname: Option<String>, roles: Option<Vec<Role>>) -> Self;
+    fn new<R: Rng + ?Sized>(rng: &mut R, age: Option<f32>, surname: Option<String>, roles: Option<Vec<Role>>) -> Self;
 }
 
 impl NewEntity for Elf {
-    fn new(age: Option<f32>, surname: Option<String>, roles: Option<Vec<Role>>) -> Self {
-        let forenames = vec!["vfaanraazr", "raazr", "moetraazr", "apeth"];
+    fn new<R: Rng + ?Sized>(rng: &mut R, age: Option<f32>, surname: Option<String>, roles: Option<Vec<Role>>) -> Self {
+        let forenames = ["vfaanraazr", "raazr", "moetraazr", "apeth"];
 
-        let surnames = vec![
+        let surnames = [
             "zroahhaa",
             "zruamoet",
             "hhaavfoetsraazr",
             "zroahhaavfoetsraazr",
         ];
 
-        let mut rng = rand::thread_rng();
-
         let name = vec![
             forenames[rng.gen_range(0..forenames.len())].to_string(),
             surname.unwrap_or_else(|| surnames[rng.gen_range(0..surnames.len())].to_string()),
@@ -31,7 +33,7 @@ impl NewEntity for Elf {
             let mut roles = Vec::new();
 
             while roles.len() < 3 {
-                roles.push(Role::random());
+                roles.push(Role::random(rng));
 
                 roles.dedup();
             }
@@ -41,7 +43,7 @@ impl NewEntity for Elf {
 
         // pad out roles if less than 3
         while roles.len() < 3 {
-            roles.push(Role::random());
+            roles.push(Role::random(rng));
 
             // ensure last role pushed is unique and is not nitwit
             if roles.len() == 3 {
@@ -59,46 +61,49 @@ impl NewEntity for Elf {
             .iter()
             .map(|role| RoleAbility {
                 role: role.clone(),
-                ability: AttributeLevel::random(),
+                ability: AttributeLevel::random(rng),
+                experience: 0,
             })
             .collect::<Vec<RoleAbility>>();
 
         Elf {
-            name: name,
+            // the stronghold gives out the real ID when the elf joins
+            id: 0,
+            name,
             age: age.unwrap_or_else(|| rng.gen_range(18.0..100.0) as i32 as f32),
             roles: finished_roles,
 
             happiness: HappinessLevel::Content,
-            patience: AttributeLevel::random(),
-            intelligence: AttributeLevel::random(),
-            strength: AttributeLevel::random(),
-            agility: AttributeLevel::random(),
-            loyalty: AttributeLevel::random(),
-            obedience: AttributeLevel::random(),
-            hunger: AttributeLevel::random(),
-            thirst: AttributeLevel::random(),
-            sleep: AttributeLevel::random(),
+            patience: AttributeLevel::random(rng),
+            intelligence: AttributeLevel::random(rng),
+            strength: AttributeLevel::random(rng),
+            agility: AttributeLevel::random(rng),
+            loyalty: AttributeLevel::random(rng),
+            obedience: AttributeLevel::random(rng),
+            hunger: AttributeLevel::random(rng),
+            thirst: AttributeLevel::random(rng),
+            sleep: AttributeLevel::random(rng),
+            asleep: false,
+            tenure: Vec::new(),
             health: 100,
 
             task: None,
-            task_start: None,
+            equipment: Equipment::default(),
         }
     }
 }
 
 impl NewEntity for Goblin {
-    fn new(age: Option<f32>, surname: Option<String>, roles: Option<Vec<Role>>) -> Self {
-        let forenames = vec!["vfaanraazr", "raazr", "moetraazr", "apeth"];
+    fn new<R: Rng + ?Sized>(rng: &mut R, age: Option<f32>, surname: Option<String>, _roles: Option<Vec<Role>>) -> Self {
+        let forenames = ["vfaanraazr", "raazr", "moetraazr", "apeth"];
 
-        let surnames = vec![
+        let surnames = [
             "zroahhaa",
             "zruamoet",
             "hhaavfoetsraazr",
             "zroahhaavfoetsraazr",
         ];
 
-        let mut rng = rand::thread_rng();
-
         let name = vec![
             forenames[rng.gen_range(0..forenames.len())].to_string(),
             surname.unwrap_or_else(|| surnames[rng.gen_range(0..surnames.len())].to_string()),
@@ -109,155 +114,453 @@ impl NewEntity for Goblin {
 
         for _ in 0..3 {
             roles.push(RoleAbility {
-                role: Role::random(),
-                ability: AttributeLevel::random(),
+                role: Role::random(rng),
+                ability: AttributeLevel::random(rng),
+                experience: 0,
             });
         }
 
         Goblin {
             name,
             age: age.unwrap_or_else(|| rng.gen_range(18.0..100.0)),
-            strength: AttributeLevel::random(),
-            agility: AttributeLevel::random(),
-            loyalty: AttributeLevel::random(),
-            charisma: AttributeLevel::random(),
+            strength: AttributeLevel::random(rng),
+            agility: AttributeLevel::random(rng),
+            loyalty: AttributeLevel::random(rng),
+            charisma: AttributeLevel::random(rng),
             health: 100,
         }
     }
 }
 
+/// how many finished or cancelled tasks a stronghold remembers
+pub const TASK_LOG_LENGTH: usize = 50;
+
 pub trait TaskOperations {
     fn new_task(&mut self, task: Task) -> Result<bool, GameError>;
+    fn assign_task(&mut self, task: Task) -> Result<bool, GameError>;
+    fn staff_task(&self, task: &Task) -> Result<(Vec<Worker>, u32), BlockedReason>;
+    fn schedule_tasks(&mut self) -> Result<(), GameError>;
     fn list_tasks(&self, elf: Option<&Elf>) -> Result<Vec<Task>, GameError>;
-    fn check_tasks_complete(&mut self, tick: u64) -> Result<Vec<Object>, GameError>;
+    fn task(&self, task_id: u32) -> Option<&Task>;
+    fn waiting_tasks(&self) -> Vec<(u32, Option<BlockedReason>)>;
+    fn free_building(&self, building_type: BuildingType, min_level: u32) -> Option<u32>;
+    fn start_tasks(&mut self, tick: u64) -> Result<(), GameError>;
+    fn check_tasks_complete(&mut self) -> Result<Vec<Stack>, GameError>;
+    fn release_task(&mut self, task: Task) -> Task;
+    fn stop_task(&mut self, task_id: u32) -> Option<Task>;
+    fn cancel_task(&mut self, task_id: u32) -> Result<Task, GameError>;
+    fn set_priority(&mut self, task_id: u32, priority: TaskPriority) -> Result<(), GameError>;
+    fn pause_task(&mut self, task_id: u32) -> Result<(), GameError>;
+    fn resume_task(&mut self, task_id: u32) -> Result<(), GameError>;
+}
+
+/// an elf's ability in a role, if it has it
+pub fn role_ability(elf: &Elf, role: &Role) -> Option<AttributeLevel> {
+    elf.roles.iter().find(|r| r.role == *role).map(|r| r.ability)
+}
+
+/// put a task in the queue, noting why it is waiting
+fn wait(stronghold: &mut Stronghold, mut task: Task, reason: BlockedReason) {
+    task.state = if reason.is_transient() {
+        TaskState::Queued
+    } else {
+        TaskState::Blocked
+    };
+    task.blocked_reason = Some(reason);
+
+    stronghold.task_queue.push(task);
+}
+
+/// whether every worker on a task is awake. a task only starts, and only gets worked on, while nobody on it is asleep
+fn workers_awake(task: &Task, elves: &[Elf]) -> bool {
+    task.workers
+        .iter()
+        .all(|worker| elves.iter().find(|elf| elf.id == worker.elf).is_some_and(|elf| !elf.asleep))
+}
+
+/// remember a task that has left the stronghold's lists
+fn log_task(stronghold: &mut Stronghold, mut task: Task, state: TaskState) -> Task {
+    task.state = state;
+    task.blocked_reason = None;
+
+    stronghold.task_log.push(task.clone());
+
+    if stronghold.task_log.len() > TASK_LOG_LENGTH {
+        stronghold.task_log.remove(0);
+    }
+
+    task
 }
 
 impl TaskOperations for Stronghold {
-    /// true - task assigned to elf
+    /// give a new task an ID and try to assign it
+    /// true - task assigned to elves
     /// false - task added to task queue
-    fn new_task(&mut self, task: Task) -> Result<bool, GameError> {
-        // first, check we have the required building(s)
-        if !self
-            .buildings
-            .iter()
-            .any(|building| building.building_type == task.required_building)
-        {
-            return Err(GameError::NoSuitableBuildingError(
-                "Missing required building".to_string(),
-            ));
+    fn new_task(&mut self, mut task: Task) -> Result<bool, GameError> {
+        // tasks that could never be done are refused outright rather than queued
+        match self.staff_task(&task) {
+            Err(BlockedReason::NoBuilding) => {
+                return Err(GameError::NoSuitableBuildingError(
+                    "Missing required building".to_string(),
+                ))
+            }
+            Err(BlockedReason::NoElf(role)) => {
+                return Err(GameError::NoSuitableElfError(format!(
+                    "Not enough elves who can work as {:?}",
+                    role
+                )))
+            }
+            _ => {}
         }
 
-        // first, check if we have a free elf
-        let mut free_elves = self
-            .elves
-            .iter_mut()
-            .filter(|elf| elf.task.is_none())
-            .collect::<Vec<&mut Elf>>();
-        // remove elves that don't have the required roles (all roles must be present)
-        for role in task.required_roles.iter() {
-            // ensure all elves have this role
-            free_elves.retain(|elf| elf.roles.iter().any(|r| r.role == *role));
-        }
+        task.id = self.next_task_id;
+        self.next_task_id += 1;
 
-        if free_elves.is_empty() {
-            // if an elf exists but isn't available, we push to the task queue
-            let mut possible_elves = self.elves.clone();
+        self.assign_task(task)
+    }
 
-            for role in task.required_roles.iter() {
-                // ensure all elves have this role
-                possible_elves.retain(|elf| elf.roles.iter().any(|r| r.role == *role));
+    /// staff a task and start it at a building with room for it, or queue it with the reason it has to wait
+    /// true - task assigned to elves
+    /// false - task added to task queue
+    fn assign_task(&mut self, mut task: Task) -> Result<bool, GameError> {
+        if task.paused {
+            wait(self, task, BlockedReason::Paused);
+            return Ok(false);
+        }
+
+        let (workers, building) = match self.staff_task(&task) {
+            Ok(staffing) => staffing,
+            Err(reason) => {
+                wait(self, task, reason);
+                return Ok(false);
             }
+        };
 
-            if !possible_elves.is_empty() {
-                self.task_queue.push(task);
+        // reserve the required objects now, so two tasks can't both count on the same ones. a task that has started before already used them up
+        if !task.consumed && task.reserved_objects.is_empty() && !task.required_objects.is_empty() {
+            if let Err(GameError::ShortfallError(shortfall)) = self.stockpile.pay(&Cost::objects(&task.required_objects)) {
+                wait(self, task, BlockedReason::MissingObjects(shortfall.objects));
                 return Ok(false);
             }
 
-            // othwise, unlikely to fixed quickly, so we error out rather than push to the task queue
-            return Err(GameError::NoSuitableElfError(
-                "No suitable elf available".to_string(),
-            ));
+            task.reserved_objects = task.required_objects.clone();
         }
 
-        // factor in elf skill level to task duration
-        let mut task = task.clone();
+        // elf skill level is factored in to task duration when it is checked (see task_duration)
+        for worker in &workers {
+            if let Some(elf) = self.elves.iter_mut().find(|elf| elf.id == worker.elf) {
+                elf.task = Some(task.id);
+            }
+        }
 
-        // find the elf with the best combined skill level of the required roles
-        let mut best_elf_position = 0;
+        // the task starts once every worker is awake
+        task.workers = workers;
+        task.building = Some(building);
+        task.started = None;
+        task.state = TaskState::Assigned;
+        task.blocked_reason = None;
+        self.active_tasks.push(task);
 
-        let mut current_best_skill = 0;
+        Ok(true)
+    }
 
-        for elf in &free_elves {
-            let mut combined_skill = 0;
+    /// pick the most able free elves for each required role (never using one elf twice) and a building with room, or say why not
+    fn staff_task(&self, task: &Task) -> Result<(Vec<Worker>, u32), BlockedReason> {
+        // first, check we have the required building(s)
+        if !self.buildings.iter().any(|building| {
+            building.building_type == task.required_building && building.level >= task.required_level
+        }) {
+            return Err(BlockedReason::NoBuilding);
+        }
 
-            for role in task.required_roles.iter() {
-                if let Some(role_ability) = elf.roles.iter().find(|r| r.role == *role) {
-                    combined_skill += role_ability.ability.clone() as i32;
-                }
+        // only elves the task is assigned to (a single elf, a work group or a profession group) are eligible
+        let members = match &task.assignee {
+            Assignee::WorkGroup(group) => self
+                .members(&task.assignee)
+                .map_err(|_| BlockedReason::NoSuchGroup(*group))?,
+            assignee => self.members(assignee).unwrap_or_default(),
+        };
+
+        let mut workers: Vec<Worker> = Vec::new();
+        let mut busy = None;
+
+        for requirement in &task.required_roles {
+            let mut candidates = self
+                .elves
+                .iter()
+                .filter(|elf| members.contains(&elf.id))
+                .filter(|elf| !workers.iter().any(|worker| worker.elf == elf.id))
+                .filter_map(|elf| role_ability(elf, &requirement.role).map(|ability| (elf, ability)))
+                .collect::<Vec<(&Elf, AttributeLevel)>>();
+
+            // not enough elves with the role at all, so this won't sort itself out soon
+            if (candidates.len() as u32) < requirement.workers {
+                return Err(BlockedReason::NoElf(requirement.role.clone()));
             }
 
-            if combined_skill > current_best_skill {
-                best_elf_position = free_elves.iter().position(|e| e == elf).unwrap();
-                current_best_skill = combined_skill;
+            // if the elves exist but aren't available, the task waits its turn
+            candidates.retain(|(elf, _)| elf.task.is_none() && !elf.asleep);
+            candidates.sort_by_key(|(_, ability)| std::cmp::Reverse(*ability as i32));
+
+            if (candidates.len() as u32) < requirement.workers {
+                busy.get_or_insert(BlockedReason::ElvesBusy(requirement.role.clone()));
+                continue;
             }
+
+            workers.extend(candidates.iter().take(requirement.workers as usize).map(|(elf, _)| Worker {
+                elf: elf.id,
+                role: requirement.role.clone(),
+            }));
         }
 
-        println!("Best elf stats: {:#?}", free_elves[best_elf_position]);
+        if let Some(reason) = busy {
+            return Err(reason);
+        }
 
-        // assign the task to the first free elf
-        free_elves[best_elf_position].task = Some(task);
-        Ok(true)
+        // a building can only run as many tasks at once as its level
+        let building = self
+            .free_building(task.required_building, task.required_level)
+            .ok_or(BlockedReason::BuildingFull)?;
+
+        Ok((workers, building))
+    }
+
+    /// offer every queued task another go, highest priority first (oldest first within a priority)
+    fn schedule_tasks(&mut self) -> Result<(), GameError> {
+        let mut queue = std::mem::take(&mut self.task_queue);
+        queue.sort_by_key(|task| std::cmp::Reverse(task.priority));
+
+        for task in queue {
+            self.assign_task(task)?;
+        }
+
+        Ok(())
     }
+
     fn list_tasks(&self, elf: Option<&Elf>) -> Result<Vec<Task>, GameError> {
         if let Some(elf) = elf {
-            if let Some(task) = &elf.task {
+            if let Some(task) = self.active_tasks.iter().find(|task| Some(task.id) == elf.task) {
                 return Ok(vec![task.clone()]);
             }
         }
 
         Ok(self.task_queue.clone())
     }
-    fn check_tasks_complete(&mut self, tick: u64) -> Result<Vec<Object>, GameError> {
+
+    /// find a task wherever it is - queued, underway or recently finished
+    fn task(&self, task_id: u32) -> Option<&Task> {
+        self.task_queue
+            .iter()
+            .chain(self.active_tasks.iter())
+            .chain(self.task_log.iter().rev())
+            .find(|task| task.id == task_id)
+    }
+
+    /// every queued task and why it is waiting (None if it hasn't been tried yet)
+    fn waiting_tasks(&self) -> Vec<(u32, Option<BlockedReason>)> {
+        self.task_queue
+            .iter()
+            .map(|task| (task.id, task.blocked_reason.clone()))
+            .collect()
+    }
+
+    /// ID of the building of a type (and at least a level) with the most room for another task
+    fn free_building(&self, building_type: BuildingType, min_level: u32) -> Option<u32> {
+        self.buildings
+            .iter()
+            .filter(|building| building.building_type == building_type && building.level >= min_level)
+            .map(|building| {
+                let running = self
+                    .active_tasks
+                    .iter()
+                    .filter(|task| task.building == Some(building.id))
+                    .count() as u32;
+
+                (building.id, building.level.saturating_sub(running))
+            })
+            .filter(|(_, room)| *room > 0)
+            .max_by_key(|(_, room)| *room)
+            .map(|(id, _)| id)
+    }
+
+    /// start every staffed task whose workers are all awake, consuming its reserved objects
+    fn start_tasks(&mut self, tick: u64) -> Result<(), GameError> {
+        for task in &mut self.active_tasks {
+            if task.started.is_some() {
+                continue;
+            }
+
+            if workers_awake(task, &self.elves) {
+                task.reserved_objects.clear();
+                task.consumed = true;
+                task.started = Some(tick);
+                task.state = TaskState::InProgress;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// work a tick on every task underway, finishing those whose time is up and adding what they produced to the stockpile.
+    /// a task whose workers have fallen asleep on it waits for them, keeping them assigned
+    fn check_tasks_complete(&mut self) -> Result<Vec<Stack>, GameError> {
         let mut completed_tasks = Vec::new();
 
-        for elf in &mut self.elves {
-            if let Some(task) = &elf.task {
-                if elf.task_start.unwrap_or(0) + task.duration as u64 >= tick {
-                    // task is complete
-                    for object in &task.produced_objects {
-                        completed_tasks.push(object.clone());
-                    }
+        let mut i = 0;
+        while i < self.active_tasks.len() {
+            let task = &mut self.active_tasks[i];
+
+            if task.started.is_none() || !workers_awake(task, &self.elves) {
+                i += 1;
+                continue;
+            }
+
+            task.progress += 1;
+
+            if task.progress < task_duration(task, &self.elves) {
+                i += 1;
+                continue;
+            }
+
+            // task is complete
+            let task = self.active_tasks.remove(i);
+            completed_tasks.extend(task_output(&task, &self.elves));
+
+            for worker in &task.workers {
+                if let Some(elf) = self.elves.iter_mut().find(|elf| elf.id == worker.elf) {
+                    gain_experience(elf, &worker.role, task.duration);
                     elf.task = None;
-                    elf.task_start = None;
                 }
             }
+
+            let task = log_task(self, task, TaskState::Done);
+
+            // build tasks raise their building once done
+            self.finish_project(task.id);
         }
 
+        self.stockpile.put_all(&completed_tasks);
+
         Ok(completed_tasks)
     }
+
+    /// put a dropped task's reserved objects back in the stockpile
+    fn release_task(&mut self, mut task: Task) -> Task {
+        self.stockpile.put_all(&task.reserved_objects);
+        task.reserved_objects.clear();
+
+        task
+    }
+
+    /// take a task off its workers and out of its building, ready to be queued again. objects are only returned if it hadn't started yet, and progress is kept
+    fn stop_task(&mut self, task_id: u32) -> Option<Task> {
+        let position = self.active_tasks.iter().position(|task| task.id == task_id)?;
+        let mut task = self.active_tasks.remove(position);
+
+        for elf in &mut self.elves {
+            if elf.task == Some(task_id) {
+                elf.task = None;
+            }
+        }
+
+        task.workers.clear();
+        task.building = None;
+        task.started = None;
+        task.state = TaskState::Queued;
+
+        Some(self.release_task(task))
+    }
+
+    /// cancel a task, whether queued or underway
+    fn cancel_task(&mut self, task_id: u32) -> Result<Task, GameError> {
+        let task = match self.task_queue.iter().position(|task| task.id == task_id) {
+            Some(position) => {
+                let task = self.task_queue.remove(position);
+                self.release_task(task)
+            }
+            None => self
+                .stop_task(task_id)
+                .ok_or_else(|| GameError::NoSuchTaskError(format!("No task {}", task_id)))?,
+        };
+
+        // a cancelled building project gives back what was paid for it
+        self.abandon_project(task_id);
+
+        Ok(log_task(self, task, TaskState::Cancelled))
+    }
+
+    fn set_priority(&mut self, task_id: u32, priority: TaskPriority) -> Result<(), GameError> {
+        self.task_queue
+            .iter_mut()
+            .chain(self.active_tasks.iter_mut())
+            .find(|task| task.id == task_id)
+            .ok_or_else(|| GameError::NoSuchTaskError(format!("No task {}", task_id)))?
+            .priority = priority;
+
+        Ok(())
+    }
+
+    /// hold a task in the queue. a task underway is stopped, and picks up where it left off once resumed
+    fn pause_task(&mut self, task_id: u32) -> Result<(), GameError> {
+        if let Some(mut task) = self.stop_task(task_id) {
+            task.paused = true;
+            wait(self, task, BlockedReason::Paused);
+            return Ok(());
+        }
+
+        let task = self
+            .task_queue
+            .iter_mut()
+            .find(|task| task.id == task_id)
+            .ok_or_else(|| GameError::NoSuchTaskError(format!("No task {}", task_id)))?;
+
+        task.paused = true;
+        task.state = TaskState::Blocked;
+        task.blocked_reason = Some(BlockedReason::Paused);
+
+        Ok(())
+    }
+
+    /// let a paused task be scheduled again
+    fn resume_task(&mut self, task_id: u32) -> Result<(), GameError> {
+        let task = self
+            .task_queue
+            .iter_mut()
+            .find(|task| task.id == task_id)
+            .ok_or_else(|| GameError::NoSuchTaskError(format!("No task {}", task_id)))?;
+
+        task.paused = false;
+        task.state = TaskState::Queued;
+        task.blocked_reason = None;
+
+        Ok(())
+    }
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum Entity {
     Elf(Elf),
     Goblin(Goblin),
 }
 
 pub trait EntityInfo {
-    fn health(&self, change: Option<i32>) -> i32;
+    fn health(&self) -> i32;
+
+    /// add to (or take from) health, returning what is left
+    fn change_health(&mut self, change: i32) -> i32;
     fn name(&self) -> String;
     fn strength(&self) -> AttributeLevel;
     fn agility(&self) -> AttributeLevel;
 }
 
 impl EntityInfo for Goblin {
-    fn health(&self, change: Option<i32>) -> i32 {
-        let mut health = self.health;
-
-        if let Some(change) = change {
-            health += change;
-        }
+    fn health(&self) -> i32 {
+        self.health
+    }
+    fn change_health(&mut self, change: i32) -> i32 {
+        self.health += change;
 
-        health
+        self.health
     }
     fn name(&self) -> String {
         self.name.join(" ")
@@ -271,14 +574,13 @@ impl EntityInfo for Goblin {
 }
 
 impl EntityInfo for Elf {
-    fn health(&self, change: Option<i32>) -> i32 {
-        let mut health = self.health;
-
-        if let Some(change) = change {
-            health += change;
-        }
+    fn health(&self) -> i32 {
+        self.health
+    }
+    fn change_health(&mut self, change: i32) -> i32 {
+        self.health += change;
 
-        health
+        self.health
     }
     fn name(&self) -> String {
         self.name.join(" ")
@@ -292,10 +594,16 @@ impl EntityInfo for Elf {
 }
 
 impl EntityInfo for Entity {
-    fn health(&self, change: Option<i32>) -> i32 {
+    fn health(&self) -> i32 {
+        match self {
+            Entity::Elf(elf) => elf.health,
+            Entity::Goblin(goblin) => goblin.health,
+        }
+    }
+    fn change_health(&mut self, change: i32) -> i32 {
         match self {
-            Entity::Elf(elf) => elf.health(change),
-            Entity::Goblin(goblin) => goblin.health(change),
+            Entity::Elf(elf) => elf.change_health(change),
+            Entity::Goblin(goblin) => goblin.change_health(change),
         }
     }
     fn name(&self) -> String {
@@ -320,64 +628,125 @@ impl EntityInfo for Entity {
 
 pub trait Fight {
     /// fight an enitity and return result of battle or error
-    fn fight(&mut self, entity: Entity) -> Result<bool, GameError>;
+    fn fight<R: Rng + ?Sized>(&mut self, entity: Entity, rng: &mut R) -> Result<bool, GameError>;
 }
 
 impl Fight for Entity {
-    fn fight(&mut self, entity: Entity) -> Result<bool, GameError> {
-        // first, calculate dodge chance (buffed by agility, nerfed by strength)
-        // then, calculate crit chance (buffed by strength, nerfed by agility)
-        // damage is just the strength
-        let mut rng = rand::thread_rng();
+    /// duel to the death, bare handed, as an encounter played out all at once. true if this entity wins
+    fn fight<R: Rng + ?Sized>(&mut self, entity: Entity, rng: &mut R) -> Result<bool, GameError> {
+        let mut encounter = Encounter::new(
+            0,
+            String::new(),
+            vec![Combatant::entity(self, Side::Attackers), Combatant::entity(&entity, Side::Defenders)],
+        );
+
+        // every hit that lands does damage, so this ends
+        while encounter.outcome.is_none() {
+            encounter.exchange(rng);
+        }
 
-        let dodge_chance = 0.5 + (self.strength() as i32 - self.agility() as i32) as f32 / 100 as f32;
+        self.change_health(encounter.combatants[0].health - self.health());
 
-        let crit_chance = 0.5 + (self.strength() as i32 - self.agility() as i32) as f32 / 100 as f32;
+        Ok(encounter.outcome == Some(Outcome::Victory(Side::Attackers)))
+    }
+}
 
-        let entity_dodge_chance =
-            0.5 + (entity.agility() as i32 - entity.strength() as i32) as f32 / 100 as f32;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log() -> Object {
+        Object {
+            resource_type: ResourceType::Wood,
+            object_type: ObjectType::RawMaterial {
+                name: "Log".to_string(),
+                description: "Test wood".to_string(),
+            },
+        }
+    }
 
-        let entity_crit_chance =
-            0.5 + (entity.strength() as i32 - entity.agility() as i32) as f32 / 100 as f32;
+    /// a farm task needing three logs, assigned in a stronghold with ten of them and everyone awake and idle
+    fn farm() -> (Stronghold, u32) {
+        let mut world = World::new(None, None, Some(17));
+        let mut stronghold = world.colonies.remove(0).strongholds.remove(0);
 
-        while self.health(None) > 0 && entity.health(None) > 0 {
-            // wait a tick
+        for elf in &mut stronghold.elves {
+            elf.task = None;
+            elf.asleep = false;
+        }
 
-            let dodge_roll = rng.gen_range(0.0..1.0);
-            let crit_roll = rng.gen_range(0.0..1.0);
-            let entity_dodge_roll = rng.gen_range(0.0..1.0);
-            let entity_crit_roll = rng.gen_range(0.0..1.0);
+        stronghold.stockpile.put(log(), 10);
 
-            if dodge_roll < entity_dodge_chance {
-                // dodge
-            } else {
-                let damage = self.strength() as i32;
+        let mut task = Task::new(
+            "Fence the fields".to_string(),
+            10,
+            BuildingType::Farm,
+            vec![RoleRequirement {
+                role: Role::Farmer,
+                workers: 1,
+            }],
+        );
+        task.required_objects = vec![Stack { object: log(), count: 3 }];
 
-                if crit_roll < crit_chance {
-                    // crit
-                    entity.health(Some(-damage * 2));
-                } else {
-                    entity.health(Some(-damage));
-                }
-            }
+        let id = stronghold.next_task_id;
+        assert!(stronghold.new_task(task).unwrap());
+        assert_eq!(stronghold.stockpile.count(&log()), 7);
 
-            // now the entity attacks
+        (stronghold, id)
+    }
 
-            if entity_dodge_roll < dodge_chance {
-                // dodge
-                continue;
-            }
+    #[test]
+    fn tasks_stopped_before_their_first_tick_pay_once() {
+        // paused and resumed
+        let (mut stronghold, id) = farm();
+        stronghold.start_tasks(0).unwrap();
 
-            let entity_damage = entity.strength() as i32;
+        stronghold.pause_task(id).unwrap();
+        stronghold.resume_task(id).unwrap();
+        stronghold.schedule_tasks().unwrap();
+        stronghold.start_tasks(1).unwrap();
 
-            if entity_crit_roll < entity_crit_chance {
-                // crit
-                self.health(Some(-entity_damage * 2));
-            } else {
-                self.health(Some(-entity_damage));
-            }
+        assert_eq!(stronghold.task(id).unwrap().state, TaskState::InProgress);
+        assert_eq!(stronghold.stockpile.count(&log()), 7);
+
+        // stopped (as when a worker dies or the building goes) and requeued
+        let (mut stronghold, id) = farm();
+        stronghold.start_tasks(0).unwrap();
+
+        let task = stronghold.stop_task(id).unwrap();
+        assert!(stronghold.assign_task(task).unwrap());
+        stronghold.start_tasks(1).unwrap();
+
+        assert_eq!(stronghold.stockpile.count(&log()), 7);
+
+        // but a task dropped before it starts gives its logs back
+        let (mut stronghold, id) = farm();
+        stronghold.cancel_task(id).unwrap();
+
+        assert_eq!(stronghold.stockpile.count(&log()), 10);
+    }
+
+    #[test]
+    fn sleeping_workers_do_no_work() {
+        let (mut stronghold, id) = farm();
+        stronghold.start_tasks(0).unwrap();
+        stronghold.check_tasks_complete().unwrap();
+
+        let worker = stronghold.task(id).unwrap().workers[0].elf;
+        let index = stronghold.elf_index(worker).unwrap();
+        stronghold.elves[index].asleep = true;
+
+        for _ in 0..3 {
+            stronghold.check_tasks_complete().unwrap();
         }
 
-        Ok(self.health(None) > 0)
+        // the task waits, still held by its worker
+        assert_eq!(stronghold.task(id).unwrap().progress, 1);
+        assert_eq!(stronghold.elves[index].task, Some(id));
+
+        stronghold.elves[index].asleep = false;
+        stronghold.check_tasks_complete().unwrap();
+
+        assert_eq!(stronghold.task(id).unwrap().progress, 2);
     }
-}
\ No newline at end of file
+}
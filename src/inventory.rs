@@ -0,0 +1,186 @@
+use crate::defs::*;
+use std::hash::{Hash, Hasher};
+
+impl ObjectType {
+    /// name of the object ("Water" for water)
+    pub fn name(&self) -> &str {
+        match self {
+            ObjectType::Food { name, .. }
+            | ObjectType::Medicine { name, .. }
+            | ObjectType::Potion { name, .. }
+            | ObjectType::Weapon { name, .. }
+            | ObjectType::Armor { name, .. }
+            | ObjectType::PickAxe { name, .. }
+            | ObjectType::RawMaterial { name, .. } => name,
+            ObjectType::Water => "Water",
+        }
+    }
+}
+
+// objects are only ever compared as item definitions, and no definition has a NaN in it
+impl Eq for Object {}
+
+/// hashes the kind, name and resource type only. equal objects always share those, so this agrees with ==
+impl Hash for Object {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&self.object_type).hash(state);
+        self.object_type.name().hash(state);
+        self.resource_type.hash(state);
+    }
+}
+
+impl From<Vec<Stack>> for Inventory {
+    fn from(stacks: Vec<Stack>) -> Self {
+        let mut inventory = Inventory::new();
+
+        for stack in stacks {
+            inventory.put(stack.object, stack.count);
+        }
+
+        inventory
+    }
+}
+
+impl From<Inventory> for Vec<Stack> {
+    fn from(inventory: Inventory) -> Self {
+        inventory.stacks.into_iter().filter(|stack| stack.count > 0).collect()
+    }
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Inventory::default()
+    }
+
+    /// how many of an object are held
+    pub fn count(&self, object: &Object) -> u32 {
+        self.index.get(object).map(|i| self.stacks[*i].count).unwrap_or(0)
+    }
+
+    /// how many objects of a resource type are held, whatever they are
+    pub fn count_resource(&self, resource_type: ResourceType) -> u32 {
+        self.resources.get(&resource_type).copied().unwrap_or(0)
+    }
+
+    /// objects held altogether
+    pub fn total(&self) -> u32 {
+        self.resources.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// every non-empty stack, in the order each kind was first put in
+    pub fn stacks(&self) -> impl Iterator<Item = &Stack> {
+        self.stacks.iter().filter(|stack| stack.count > 0)
+    }
+
+    /// the first object held that matches (ex. any food)
+    pub fn find(&self, predicate: impl Fn(&Object) -> bool) -> Option<&Object> {
+        self.stacks().map(|stack| &stack.object).find(|object| predicate(object))
+    }
+
+    pub fn put(&mut self, object: Object, count: u32) {
+        if count == 0 {
+            return;
+        }
+
+        *self.resources.entry(object.resource_type).or_insert(0) += count;
+
+        match self.index.get(&object) {
+            Some(i) => self.stacks[*i].count += count,
+            None => {
+                self.index.insert(object.clone(), self.stacks.len());
+                self.stacks.push(Stack { object, count });
+            }
+        }
+    }
+
+    pub fn put_all(&mut self, stacks: &[Stack]) {
+        for stack in stacks {
+            self.put(stack.object.clone(), stack.count);
+        }
+    }
+
+    /// take a number of an object, or none at all if there aren't enough
+    pub fn take(&mut self, object: &Object, count: u32) -> bool {
+        let Some(i) = self.index.get(object).copied() else {
+            return count == 0;
+        };
+
+        if self.stacks[i].count < count {
+            return false;
+        }
+
+        self.stacks[i].count -= count;
+        *self.resources.entry(object.resource_type).or_insert(0) -= count;
+
+        true
+    }
+
+    /// take every wanted stack, or nothing at all if any are short
+    pub fn take_all(&mut self, wanted: &[Stack]) -> bool {
+        if !self.missing(wanted).is_empty() {
+            return false;
+        }
+
+        for stack in wanted {
+            self.take(&stack.object, stack.count);
+        }
+
+        true
+    }
+
+    /// take a number of objects of a resource type, from the oldest stacks first, or none at all if there aren't enough. returns what was taken
+    pub fn take_resource(&mut self, resource_type: ResourceType, count: u32) -> Option<Vec<Stack>> {
+        if self.count_resource(resource_type) < count {
+            return None;
+        }
+
+        let mut left = count;
+        let mut taken = Vec::new();
+
+        for stack in &mut self.stacks {
+            if left == 0 {
+                break;
+            }
+
+            if stack.object.resource_type == resource_type && stack.count > 0 {
+                let count = stack.count.min(left);
+                stack.count -= count;
+                left -= count;
+
+                taken.push(Stack {
+                    object: stack.object.clone(),
+                    count,
+                });
+            }
+        }
+
+        *self.resources.entry(resource_type).or_insert(0) -= count;
+
+        Some(taken)
+    }
+
+    /// how many of each wanted object are short
+    pub fn missing(&self, wanted: &[Stack]) -> Vec<Stack> {
+        let mut needed: Vec<Stack> = Vec::new();
+
+        // the same object may be wanted in more than one stack
+        for stack in wanted {
+            match needed.iter_mut().find(|n| n.object == stack.object) {
+                Some(n) => n.count += stack.count,
+                None => needed.push(stack.clone()),
+            }
+        }
+
+        needed
+            .into_iter()
+            .filter_map(|stack| {
+                let short = stack.count.saturating_sub(self.count(&stack.object));
+                (short > 0).then_some(Stack { object: stack.object, count: short })
+            })
+            .collect()
+    }
+}
@@ -1,53 +1,159 @@
 pub mod defs;
 pub mod impls;
 pub mod game;
+pub mod save;
+pub mod migrate;
+pub mod rng;
+pub mod battle;
+pub mod clock;
+pub mod combat;
+pub mod needs;
+pub mod death;
+pub mod happiness;
+pub mod skills;
+pub mod groups;
+pub mod costs;
+pub mod construction;
+pub mod durability;
+pub mod equipment;
+pub mod goblins;
+pub mod stats;
+pub mod inventory;
+pub mod orders;
+pub mod recipes;
 
 use {
+    battle::*,
+    clock::*,
+    combat::*,
+    construction::*,
+    equipment::*,
     defs::*,
-    impls::*,
     game::*,
+    goblins::*,
+    impls::*,
+    orders::*,
+    recipes::*,
+    save::*,
+    stats::*,
 };
 
-fn main() {
-    let mut world = World::new(Some("World".to_string()), Some("Player".to_string()));
+fn main() -> Result<(), GameError> {
+    let mut world = World::new(Some("World".to_string()), Some("Player".to_string()), None);
 
     println!("{:#?}", world.colonies[0].strongholds[0].elves[0]);
 
     // assign test task and see if it gets assigned to the best elf
-    let task = Task {
-        description: "Test Task".to_string(),
-        required_roles: vec![Role::Trader, Role::Warrior],
-        duration: 10,
-        required_building: BuildingType::MeetingHall,
-        required_objects: vec![],
-        produced_objects: vec![],
-    };
+    let task = Task::new(
+        "Test Task".to_string(),
+        10,
+        BuildingType::MeetingHall,
+        vec![
+            RoleRequirement { role: Role::Trader, workers: 1 },
+            RoleRequirement { role: Role::Warrior, workers: 1 },
+        ],
+    );
 
-    world.colonies[0].strongholds[0].task_queue.push(task.clone());
+    world.colonies[0].strongholds[0].new_task(task.clone())?;
 
-    world.tick();
+    world.tick()?;
 
     println!("{:#?}", world.colonies[0].strongholds[0].task_queue);
 
-    // now upgrade an elf
-    // add 5 wood to the stronghold
-    for _ in 0..15 {
-        world.colonies[0].strongholds[0].stockpile.push(Object {
+    // now upgrade the farm: a builder joins, and we add the wood and plants it costs
+    let builder = Elf::new(&mut world.rng, None, None, Some(vec![Role::Builder]));
+    world.colonies[0].strongholds[0].recruit(builder)?;
+
+    world.colonies[0].strongholds[0].stockpile.put(
+        Object {
+            resource_type: ResourceType::Wood,
+            object_type: ObjectType::RawMaterial {
+                name: "Log".to_string(),
+                description: "A length of tree trunk".to_string(),
+            },
+        },
+        6,
+    );
+
+    world.colonies[0].strongholds[0].stockpile.put(
+        Object {
             resource_type: ResourceType::Plant,
             object_type: ObjectType::RawMaterial {
                 name: "Plant".to_string(),
                 description: "It's green and (possibly) edible".to_string(),
             },
-        });
-    }
+        },
+        15,
+    );
 
-    let elf = &mut world.colonies[0].strongholds[0].elves[0];
     println!("{:#?}", world.colonies[0].strongholds[0].buildings);
-    println!("{:#?}", world.colonies[0].strongholds[0].upgrade_building(BuildingType::Farm));
+    let farm = world.colonies[0].strongholds[0]
+        .buildings
+        .iter()
+        .find(|building| building.building_type == BuildingType::Farm)
+        .map(|building| building.id)
+        .unwrap_or_default();
 
-    println!("{:#?}", world.colonies[0].strongholds[0].buildings);
+    println!("{:#?}", world.colonies[0].strongholds[0].upgrade(farm));
+    println!("{:#?}", world.colonies[0].strongholds[0].projects);
+
+    // keep farmers harvesting until there are 20 plants in stock
+    let recipes = RecipeBook::default();
+    let harvest = world.colonies[0].strongholds[0].recipe_task(recipes.recipe("Harvest")?)?;
+    let plant = harvest.produced_objects[0].object.clone();
+
+    world.colonies[0].strongholds[0].add_order(harvest, OrderKind::KeepStocked { object: plant, target: 20 });
+
+    // there is no kitchen yet, so nothing can be cooked
+    println!("{:?}", world.colonies[0].strongholds[0].craft(recipes.recipe("Cook Meal")?));
 
-    
+    // hand the first elf a pick axe from the stockpile
+    let pick_axe = recipes.recipe("Forge Iron Pick Axe")?.outputs[0].object.clone();
+    let elf = world.colonies[0].strongholds[0].elves[0].id;
+    world.colonies[0].strongholds[0].stockpile.put(pick_axe.clone(), 1);
+    println!("{:?}", world.colonies[0].strongholds[0].equip(elf, &pick_axe));
+    println!("{:?}", world.colonies[0].strongholds[0].elves[0].effective_agility());
 
+    // a goblin turns up at the gates, and the stronghold's first elf goes out to meet it
+    let goblin = Goblin::new(&mut world.rng, None, None, None);
+    let defender = Combatant::elf(&world.colonies[0].strongholds[0], &world.colonies[0].strongholds[0].elves[0], Side::Defenders);
+    let stronghold = world.colonies[0].strongholds[0].name.clone();
+    let fight = world.start_encounter(&stronghold, vec![Combatant::goblin(&goblin, Side::Attackers), defender])?;
+
+    world.tick()?;
+    println!("{:#?}", world.encounter(fight)?.log);
+
+    // a goblin warband raids the stronghold, fought out over the next ticks
+    let raiders = (0..3)
+        .map(|_| Combatant::goblin(&Goblin::new(&mut world.rng, None, None, None), Side::Attackers))
+        .collect();
+    let battle = world.start_battle(&stronghold, raiders, None)?;
+
+    while world.battle_report(battle)?.is_none() {
+        world.tick()?;
+    }
+
+    println!("{:#?}", world.battle_report(battle)?);
+
+    // goblins have settled nearby, and will raid once they feel strong enough
+    if let Some(goblins) = world.goblin_stronghold("Goblin Warren") {
+        println!("{} goblins at {}, eyeing {}", goblins.goblins.len(), goblins.name, goblins.colony);
+    }
+
+    // run the world in real time for a moment, at normal then double speed
+    let game_loop = GameLoop::start(world);
+    std::thread::sleep(std::time::Duration::from_millis(250));
+    game_loop.set_speed(Speed::Double);
+    std::thread::sleep(std::time::Duration::from_millis(250));
+    println!("{:?} at tick {}", game_loop.status(), game_loop.with_world(|world| world.tick));
+    let world = game_loop.stop()?;
+
+    // save to a slot and list what is on disk
+    world.save()?;
+
+    for slot in SaveDirectory::default().list()? {
+        println!("{}: {:?}", slot.name, slot.header);
+    }
 
+    Ok(())
 }
@@ -0,0 +1,584 @@
+use crate::game::*;
+use serde_json::{json, Value};
+
+/// current save schema version. bump this and append to MIGRATIONS whenever a saved type changes shape
+pub const SAVE_VERSION: u32 = 23;
+
+/// a migration upgrades a saved world document from one version to the next
+pub type Migration = fn(Value) -> Result<Value, GameError>;
+
+/// MIGRATIONS[n] upgrades a world saved at version n to version n + 1
+pub const MIGRATIONS: [Migration; SAVE_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8, v8_to_v9, v9_to_v10, v10_to_v11, v11_to_v12, v12_to_v13, v13_to_v14, v14_to_v15, v15_to_v16, v16_to_v17, v17_to_v18, v18_to_v19, v19_to_v20, v20_to_v21, v21_to_v22, v22_to_v23];
+
+/// upgrade a saved world document from `version` to SAVE_VERSION, one version at a time
+pub fn migrate(mut world: Value, version: u32) -> Result<Value, GameError> {
+    if version > SAVE_VERSION {
+        return Err(GameError::LoadError(format!(
+            "Save version {} is newer than this game supports ({})",
+            version, SAVE_VERSION
+        )));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        world = migration(world)?;
+    }
+
+    Ok(world)
+}
+
+/// look up a field that every saved world has, for migrations to walk down
+pub fn field_mut<'a>(value: &'a mut Value, field: &str) -> Result<&'a mut Value, GameError> {
+    value
+        .get_mut(field)
+        .ok_or_else(|| GameError::LoadError(format!("Save is missing field {}", field)))
+}
+
+/// borrow a saved value as a list
+pub fn array_mut(value: &mut Value) -> Result<&mut Vec<Value>, GameError> {
+    value
+        .as_array_mut()
+        .ok_or_else(|| GameError::LoadError("Expected a list in save".to_string()))
+}
+
+/// run a function over every stronghold in a saved world
+pub fn for_each_stronghold(
+    world: &mut Value,
+    mut f: impl FnMut(&mut Value) -> Result<(), GameError>,
+) -> Result<(), GameError> {
+    for colony in array_mut(field_mut(world, "colonies")?)? {
+        for stronghold in array_mut(field_mut(colony, "strongholds")?)? {
+            f(stronghold)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// run a function over every elf in a saved world
+pub fn for_each_elf(
+    world: &mut Value,
+    mut f: impl FnMut(&mut Value) -> Result<(), GameError>,
+) -> Result<(), GameError> {
+    for_each_stronghold(world, |stronghold| {
+        for elf in array_mut(field_mut(stronghold, "elves")?)? {
+            f(elf)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// run a function over every task in a saved world, queued or held by an elf (as tasks were kept before version 9)
+pub fn for_each_task(
+    world: &mut Value,
+    mut f: impl FnMut(&mut Value) -> Result<(), GameError>,
+) -> Result<(), GameError> {
+    for_each_stronghold(world, |stronghold| {
+        for task in array_mut(field_mut(stronghold, "task_queue")?)? {
+            f(task)?;
+        }
+
+        for elf in array_mut(field_mut(stronghold, "elves")?)? {
+            let task = field_mut(elf, "task")?;
+
+            if !task.is_null() {
+                f(task)?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// insert a field into a saved object, failing if the value isn't an object
+pub fn insert(value: &mut Value, field: &str, inserted: Value) -> Result<(), GameError> {
+    value
+        .as_object_mut()
+        .ok_or_else(|| GameError::LoadError(format!("Expected an object to hold {} in save", field)))?
+        .insert(field.to_string(), inserted);
+
+    Ok(())
+}
+
+/// version 0 is the headerless world.json, version 1 added the slot header. the world itself did not change shape
+fn v0_to_v1(world: Value) -> Result<Value, GameError> {
+    Ok(world)
+}
+
+/// version 2 seeds the world. older worlds had no seed, so they all continue from seed 0
+fn v1_to_v2(mut world: Value) -> Result<Value, GameError> {
+    insert(&mut world, "seed", json!(0))?;
+    insert(&mut world, "rng", json!({ "state": 0 }))?;
+
+    Ok(world)
+}
+
+/// version 3 tracks whether elves are asleep. everyone wakes up on load
+fn v2_to_v3(mut world: Value) -> Result<Value, GameError> {
+    for_each_elf(&mut world, |elf| insert(elf, "asleep", json!(false)))?;
+
+    Ok(world)
+}
+
+/// version 4 keeps a log of world events, which starts empty
+fn v3_to_v4(mut world: Value) -> Result<Value, GameError> {
+    insert(&mut world, "events", json!([]))?;
+
+    Ok(world)
+}
+
+/// version 5 tracks how long elves have been elders or leaders. past service is forgotten
+fn v4_to_v5(mut world: Value) -> Result<Value, GameError> {
+    for_each_elf(&mut world, |elf| insert(elf, "tenure", json!([])))?;
+
+    Ok(world)
+}
+
+/// version 6 reserves a task's required objects while it waits to start. nothing was ever reserved before
+fn v5_to_v6(mut world: Value) -> Result<Value, GameError> {
+    for_each_task(&mut world, |task| insert(task, "reserved_objects", json!([])))?;
+
+    Ok(world)
+}
+
+/// version 7 gives roles experience towards their next level, starting from none
+fn v6_to_v7(mut world: Value) -> Result<Value, GameError> {
+    for_each_elf(&mut world, |elf| {
+        for role in array_mut(field_mut(elf, "roles")?)? {
+            insert(role, "experience", json!(0))?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(world)
+}
+
+/// version 8 gives elves IDs so they can be put in work groups, and lets tasks target a group. elves are numbered in roster order
+fn v7_to_v8(mut world: Value) -> Result<Value, GameError> {
+    for_each_stronghold(&mut world, |stronghold| {
+        let elves = array_mut(field_mut(stronghold, "elves")?)?;
+        let count = elves.len();
+
+        for (id, elf) in elves.iter_mut().enumerate() {
+            insert(elf, "id", json!(id))?;
+        }
+
+        insert(stronghold, "next_elf_id", json!(count))?;
+        insert(stronghold, "work_groups", json!([]))?;
+        insert(stronghold, "next_work_group_id", json!(0))
+    })?;
+
+    for_each_task(&mut world, |task| insert(task, "assignee", json!("Anyone")))?;
+
+    Ok(world)
+}
+
+/// version 9 staffs tasks with several elves, one per required worker, and tracks them on the stronghold by ID.
+/// tasks elves were working on go back to the front of the queue (keeping anything reserved for them)
+fn v8_to_v9(mut world: Value) -> Result<Value, GameError> {
+    for_each_stronghold(&mut world, |stronghold| {
+        let mut requeued = Vec::new();
+
+        for elf in array_mut(field_mut(stronghold, "elves")?)? {
+            let task = field_mut(elf, "task")?.take();
+
+            if !task.is_null() {
+                requeued.push(task);
+            }
+
+            insert(elf, "task", Value::Null)?;
+
+            if let Some(elf) = elf.as_object_mut() {
+                elf.remove("task_start");
+            }
+        }
+
+        let queue = array_mut(field_mut(stronghold, "task_queue")?)?;
+        requeued.append(queue);
+        *queue = requeued;
+
+        for (id, task) in queue.iter_mut().enumerate() {
+            let roles = field_mut(task, "required_roles")?.take();
+            let requirements = roles
+                .as_array()
+                .map(|roles| roles.iter().map(|role| json!({ "role": role, "workers": 1 })).collect::<Vec<Value>>())
+                .unwrap_or_default();
+
+            insert(task, "id", json!(id))?;
+            insert(task, "required_roles", json!(requirements))?;
+            insert(task, "workers", json!([]))?;
+            insert(task, "building", Value::Null)?;
+            insert(task, "started", Value::Null)?;
+        }
+
+        let count = queue.len();
+
+        insert(stronghold, "active_tasks", json!([]))?;
+        insert(stronghold, "next_task_id", json!(count))
+    })?;
+
+    Ok(world)
+}
+
+/// version 10 gives tasks a priority and a state, and keeps a log of finished ones. tasks already staffed are assigned or underway
+fn v9_to_v10(mut world: Value) -> Result<Value, GameError> {
+    for_each_stronghold(&mut world, |stronghold| {
+        for list in ["task_queue", "active_tasks"] {
+            for task in array_mut(field_mut(stronghold, list)?)? {
+                let state = match list {
+                    "task_queue" => "Queued",
+                    _ if field_mut(task, "started")?.is_null() => "Assigned",
+                    _ => "InProgress",
+                };
+
+                insert(task, "priority", json!("Normal"))?;
+                insert(task, "state", json!(state))?;
+                insert(task, "blocked_reason", Value::Null)?;
+                insert(task, "paused", json!(false))?;
+            }
+        }
+
+        insert(stronghold, "task_log", json!([]))
+    })?;
+
+    Ok(world)
+}
+
+/// version 11 adds standing orders. older strongholds have none
+fn v10_to_v11(mut world: Value) -> Result<Value, GameError> {
+    for_each_stronghold(&mut world, |stronghold| {
+        insert(stronghold, "standing_orders", json!([]))?;
+        insert(stronghold, "next_order_id", json!(0))?;
+        insert(stronghold, "orders_paused", json!(false))
+    })?;
+
+    Ok(world)
+}
+
+/// version 12 lets tasks need a minimum building level. every older task could be done at any level
+fn v11_to_v12(mut world: Value) -> Result<Value, GameError> {
+    for_each_stronghold(&mut world, |stronghold| {
+        for list in ["task_queue", "active_tasks", "task_log"] {
+            for task in array_mut(field_mut(stronghold, list)?)? {
+                insert(task, "required_level", json!(1))?;
+            }
+        }
+
+        for order in array_mut(field_mut(stronghold, "standing_orders")?)? {
+            insert(field_mut(order, "task")?, "required_level", json!(1))?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(world)
+}
+
+/// a saved list of objects as a list of stacks, in the order each kind first appears
+fn stack_objects(objects: &mut Value) -> Result<(), GameError> {
+    let mut stacks: Vec<Value> = Vec::new();
+
+    for object in array_mut(objects)?.drain(..) {
+        match stacks.iter_mut().find(|stack| stack["object"] == object) {
+            Some(stack) => stack["count"] = json!(stack["count"].as_u64().unwrap_or(0) + 1),
+            None => stacks.push(json!({ "object": object, "count": 1 })),
+        }
+    }
+
+    *objects = json!(stacks);
+
+    Ok(())
+}
+
+/// version 13 counts objects in stacks rather than keeping one per unit, in the stockpile and in tasks
+fn v12_to_v13(mut world: Value) -> Result<Value, GameError> {
+    let stack_task = |task: &mut Value| -> Result<(), GameError> {
+        for field in ["required_objects", "produced_objects", "reserved_objects"] {
+            stack_objects(field_mut(task, field)?)?;
+        }
+
+        if let Some(missing) = task.get_mut("blocked_reason").and_then(|reason| reason.get_mut("MissingObjects")) {
+            stack_objects(missing)?;
+        }
+
+        Ok(())
+    };
+
+    for_each_stronghold(&mut world, |stronghold| {
+        stack_objects(field_mut(stronghold, "stockpile")?)?;
+
+        for list in ["task_queue", "active_tasks", "task_log"] {
+            for task in array_mut(field_mut(stronghold, list)?)? {
+                stack_task(task)?;
+            }
+        }
+
+        for order in array_mut(field_mut(stronghold, "standing_orders")?)? {
+            stack_task(field_mut(order, "task")?)?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(world)
+}
+
+/// version 14 counts the ticks worked on each task, and adds building projects. tasks underway have worked every tick since they started
+fn v13_to_v14(mut world: Value) -> Result<Value, GameError> {
+    let tick = world["tick"].as_u64().unwrap_or(0);
+
+    for_each_stronghold(&mut world, |stronghold| {
+        for list in ["task_queue", "active_tasks", "task_log"] {
+            for task in array_mut(field_mut(stronghold, list)?)? {
+                let progress = field_mut(task, "started")?
+                    .as_u64()
+                    .map(|started| tick.saturating_sub(started))
+                    .unwrap_or(0);
+
+                insert(task, "progress", json!(progress))?;
+            }
+        }
+
+        for order in array_mut(field_mut(stronghold, "standing_orders")?)? {
+            insert(field_mut(order, "task")?, "progress", json!(0))?;
+        }
+
+        insert(stronghold, "projects", json!([]))
+    })?;
+
+    Ok(world)
+}
+
+/// version 15 gives out building IDs from a counter, starting after the highest in use
+fn v14_to_v15(mut world: Value) -> Result<Value, GameError> {
+    for_each_stronghold(&mut world, |stronghold| {
+        let next = array_mut(field_mut(stronghold, "buildings")?)?
+            .iter()
+            .filter_map(|building| building["id"].as_u64())
+            .map(|id| id + 1)
+            .max()
+            .unwrap_or(0);
+
+        insert(stronghold, "next_building_id", json!(next))
+    })?;
+
+    Ok(world)
+}
+
+/// version 16 gives buildings hit points. every older building is undamaged
+fn v15_to_v16(mut world: Value) -> Result<Value, GameError> {
+    for_each_stronghold(&mut world, |stronghold| {
+        for building in array_mut(field_mut(stronghold, "buildings")?)? {
+            // 100 hit points a level, as HEALTH_PER_LEVEL was in version 16
+            let level = building["level"].as_u64().unwrap_or(1);
+
+            insert(building, "health", json!(100 * level))?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(world)
+}
+
+/// version 17 gives strongholds stats, all at level 1, and lets elves travel between them
+fn v16_to_v17(mut world: Value) -> Result<Value, GameError> {
+    for colony in array_mut(field_mut(&mut world, "colonies")?)? {
+        insert(colony, "travellers", json!([]))?;
+    }
+
+    for_each_stronghold(&mut world, |stronghold| {
+        insert(stronghold, "stats", json!({ "size": 1, "movement": 1, "fortification": 1 }))
+    })?;
+
+    Ok(world)
+}
+
+/// version 18 keeps fights in the world. older worlds have none underway
+fn v17_to_v18(mut world: Value) -> Result<Value, GameError> {
+    insert(&mut world, "encounters", json!([]))?;
+    insert(&mut world, "next_encounter_id", json!(0))?;
+
+    Ok(world)
+}
+
+/// version 19 gives combatants weapons and armor, and logs what armor blocked. fights already underway were bare handed
+fn v18_to_v19(mut world: Value) -> Result<Value, GameError> {
+    for encounter in array_mut(field_mut(&mut world, "encounters")?)? {
+        for combatant in array_mut(field_mut(encounter, "combatants")?)? {
+            insert(combatant, "weapon", Value::Null)?;
+            insert(combatant, "armor", Value::Null)?;
+        }
+
+        for roll in array_mut(field_mut(encounter, "log")?)? {
+            insert(roll, "blocked", json!(0))?;
+        }
+    }
+
+    Ok(world)
+}
+
+/// version 20 gives elves equipment slots. elves start with nothing equipped, including those on the road
+fn v19_to_v20(mut world: Value) -> Result<Value, GameError> {
+    let empty = || json!({ "main_hand": null, "body_armor": null, "tool": null });
+
+    for_each_elf(&mut world, |elf| insert(elf, "equipment", empty()))?;
+
+    for colony in array_mut(field_mut(&mut world, "colonies")?)? {
+        for traveller in array_mut(field_mut(colony, "travellers")?)? {
+            insert(field_mut(traveller, "elf")?, "equipment", empty())?;
+        }
+    }
+
+    Ok(world)
+}
+
+/// version 21 adds battles, and combatants' loyalty for holding their nerve in them. fights already underway are duels
+fn v20_to_v21(mut world: Value) -> Result<Value, GameError> {
+    for encounter in array_mut(field_mut(&mut world, "encounters")?)? {
+        insert(encounter, "battle", Value::Null)?;
+
+        for combatant in array_mut(field_mut(encounter, "combatants")?)? {
+            insert(combatant, "loyalty", json!("Average"))?;
+        }
+    }
+
+    Ok(world)
+}
+
+/// version 22 puts goblin strongholds on the map. goblins only settle near new worlds
+fn v21_to_v22(mut world: Value) -> Result<Value, GameError> {
+    insert(&mut world, "goblin_strongholds", json!([]))?;
+
+    Ok(world)
+}
+
+/// version 23 records whether a task has used up its required objects. older tasks had if they were underway or had been worked on
+fn v22_to_v23(mut world: Value) -> Result<Value, GameError> {
+    for_each_stronghold(&mut world, |stronghold| {
+        for list in ["task_queue", "active_tasks", "task_log"] {
+            for task in array_mut(field_mut(stronghold, list)?)? {
+                let consumed = !field_mut(task, "started")?.is_null() || field_mut(task, "progress")?.as_u64().unwrap_or(0) > 0;
+
+                insert(task, "consumed", json!(consumed))?;
+            }
+        }
+
+        for order in array_mut(field_mut(stronghold, "standing_orders")?)? {
+            insert(field_mut(order, "task")?, "consumed", json!(false))?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(world)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{durability::*, save::*};
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    /// a save from every past version, written by the game as it was at that version (v0 is the headerless world.json)
+    fn fixtures() -> SaveDirectory {
+        SaveDirectory::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/saves"))
+    }
+
+    /// the fixture's world document as it was saved, before migration
+    fn raw_world(version: u32) -> Value {
+        let path = fixtures().path.join(format!("v{}.json", version));
+        let contents = std::fs::read_to_string(path).unwrap();
+
+        serde_json::from_str(contents.lines().last().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn every_version_has_a_fixture() {
+        for version in 0..=SAVE_VERSION {
+            assert!(
+                fixtures().path.join(format!("v{}.json", version)).is_file(),
+                "missing fixture for save version {}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn fixtures_are_saved_at_their_version() {
+        for version in 1..=SAVE_VERSION {
+            assert_eq!(fixtures().header(&format!("v{}", version)).unwrap().version, version);
+        }
+    }
+
+    #[test]
+    fn every_version_loads_and_migrates() {
+        for version in 0..=SAVE_VERSION {
+            let slot = format!("v{}", version);
+            let mut world = fixtures().load(&slot).unwrap_or_else(|error| panic!("{} failed to load: {:?}", slot, error));
+            let raw = raw_world(version);
+
+            for (s, stronghold) in world.colonies.iter().flat_map(|colony| colony.strongholds.iter()).enumerate() {
+                // task IDs are unique and below the next one handed out
+                let ids = stronghold
+                    .task_queue
+                    .iter()
+                    .chain(stronghold.active_tasks.iter())
+                    .map(|task| task.id)
+                    .collect::<Vec<u32>>();
+
+                assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len(), "{} has duplicate task IDs", slot);
+                assert!(
+                    ids.iter().chain(stronghold.task_log.iter().map(|task| &task.id)).all(|id| *id < stronghold.next_task_id),
+                    "{} has a task ID at or past next_task_id",
+                    slot
+                );
+
+                // the stockpile is stacked, one stack per kind of object, and nothing went missing on the way
+                let objects = stronghold.stockpile.stacks().map(|stack| &stack.object).collect::<HashSet<_>>();
+                assert_eq!(objects.len(), stronghold.stockpile.stacks().count(), "{} has unmerged stacks", slot);
+
+                if version < 13 {
+                    let saved = raw["colonies"][0]["strongholds"][s]["stockpile"].as_array().unwrap().len();
+                    assert_eq!(stronghold.stockpile.total() as usize, saved, "{} lost stock when stacking", slot);
+                }
+
+                // buildings have unique IDs and health for their level
+                let buildings = stronghold.buildings.iter().map(|building| building.id).collect::<HashSet<_>>();
+                assert_eq!(buildings.len(), stronghold.buildings.len(), "{} has duplicate building IDs", slot);
+
+                for building in &stronghold.buildings {
+                    assert!(building.id < stronghold.next_building_id, "{} would reuse building ID {}", slot, building.id);
+                    assert!(building.health > 0 && building.health <= max_health(building.level));
+
+                    if version < 16 {
+                        assert_eq!(building.health, max_health(building.level), "{} building not at full health", slot);
+                    }
+                }
+            }
+
+            // goblins only settle near worlds made since version 22
+            if version < 22 {
+                assert!(world.goblin_strongholds.is_empty());
+            } else {
+                assert_eq!(
+                    world.goblin_strongholds.len(),
+                    raw["goblin_strongholds"].as_array().unwrap().len()
+                );
+                assert!(world.goblin_strongholds.iter().all(|goblins| !goblins.goblins.is_empty() || !goblins.raiders.is_empty()));
+            }
+
+            // and the migrated world runs
+            for _ in 0..20 {
+                world.tick().unwrap_or_else(|error| panic!("{} failed to tick: {:?}", slot, error));
+            }
+        }
+    }
+
+    #[test]
+    fn headerless_save_is_version_0() {
+        assert_eq!(fixtures().header("v0").unwrap().version, 0);
+    }
+}
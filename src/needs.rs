@@ -0,0 +1,198 @@
+use crate::{clock::*, defs::*, game::*, rng::*};
+use rand::Rng;
+
+/// in game hour, in ticks (one real minute at normal speed)
+pub const TICKS_PER_HOUR: u64 = TICKS_PER_SECOND as u64 * 60;
+
+/// hunger drops one level every this many ticks
+pub const HUNGER_DECAY_TICKS: u64 = TICKS_PER_HOUR * 4;
+
+/// thirst drops one level every this many ticks
+pub const THIRST_DECAY_TICKS: u64 = TICKS_PER_HOUR * 2;
+
+/// sleep drops one level every this many ticks while awake (Excellent to Terrible over a 16 hour day)
+pub const SLEEP_DECAY_TICKS: u64 = TICKS_PER_HOUR * 4;
+
+/// sleep rises one level every this many ticks while asleep (Terrible to Excellent over 8 hours)
+pub const SLEEP_RECOVERY_TICKS: u64 = TICKS_PER_HOUR * 2;
+
+/// elves eat once hunger is at or below this level
+pub const EAT_AT: AttributeLevel = AttributeLevel::Average;
+
+/// elves drink once thirst is at or below this level
+pub const DRINK_AT: AttributeLevel = AttributeLevel::Average;
+
+/// hunger or thirst at this level is critical
+pub const CRITICAL_NEED: AttributeLevel = AttributeLevel::Terrible;
+
+/// happiness at or below this level is critical
+pub const CRITICAL_HAPPINESS: HappinessLevel = HappinessLevel::Miserable;
+
+/// critical needs deal damage (and healthy elves recover) every this many ticks
+pub const CRITICAL_DAMAGE_TICKS: u64 = TICKS_PER_HOUR / 6;
+
+/// damage dealt by each critical need
+pub const CRITICAL_DAMAGE: i32 = 2;
+
+/// health recovered once no need is critical
+pub const RECOVERY: i32 = 1;
+
+/// health of an unhurt elf
+pub const MAX_HEALTH: i32 = 100;
+
+pub trait Needs {
+    fn tick_needs(&mut self, tick: u64, rng: &mut GameRng) -> Result<(), GameError>;
+    fn tick_health(&mut self, tick: u64) -> Result<(), GameError>;
+    fn eat(&mut self, elf_index: usize, rng: &mut GameRng) -> Result<bool, GameError>;
+    fn drink(&mut self, elf_index: usize) -> Result<bool, GameError>;
+}
+
+/// the needs (in order hunger, thirst, happiness) that are critical for an elf, as the death they would cause
+pub fn critical_needs(elf: &Elf) -> Vec<DeathCause> {
+    let mut critical = Vec::new();
+
+    if elf.hunger == CRITICAL_NEED {
+        critical.push(DeathCause::Starvation);
+    }
+
+    if elf.thirst == CRITICAL_NEED {
+        critical.push(DeathCause::Dehydration);
+    }
+
+    if elf.happiness.clone() as i32 <= CRITICAL_HAPPINESS as i32 {
+        critical.push(DeathCause::Despair);
+    }
+
+    critical
+}
+
+/// whether a decay of the given period falls on this tick
+fn due(tick: u64, period: u64) -> bool {
+    tick > 0 && tick.is_multiple_of(period)
+}
+
+impl Needs for Stronghold {
+    /// decay needs, send tired elves to bed, wake rested ones and feed the hungry and thirsty from the stockpile
+    fn tick_needs(&mut self, tick: u64, rng: &mut GameRng) -> Result<(), GameError> {
+        for elf_index in 0..self.elves.len() {
+            let elf = &mut self.elves[elf_index];
+
+            if due(tick, HUNGER_DECAY_TICKS) {
+                elf.hunger = elf.hunger.lower(1);
+            }
+
+            if due(tick, THIRST_DECAY_TICKS) {
+                elf.thirst = elf.thirst.lower(1);
+            }
+
+            if elf.asleep {
+                if due(tick, SLEEP_RECOVERY_TICKS) {
+                    elf.sleep = elf.sleep.raise(1);
+                }
+
+                // rested, or woken by a critical need
+                let woken = elf.hunger == CRITICAL_NEED || elf.thirst == CRITICAL_NEED;
+
+                if elf.sleep == AttributeLevel::Excellent || woken {
+                    elf.asleep = false;
+                }
+
+                if !woken {
+                    continue;
+                }
+            }
+
+            if due(tick, SLEEP_DECAY_TICKS) {
+                elf.sleep = elf.sleep.lower(1);
+            }
+
+            // idle elves nap once tired, busy ones only once exhausted. nobody sleeps through starving or dehydrating
+            let critical = elf.hunger == CRITICAL_NEED || elf.thirst == CRITICAL_NEED;
+
+            if !critical
+                && (elf.sleep == AttributeLevel::Terrible
+                    || (elf.task.is_none() && elf.sleep as i32 <= AttributeLevel::Poor as i32))
+            {
+                elf.asleep = true;
+                continue;
+            }
+
+            if elf.hunger as i32 <= EAT_AT as i32 {
+                self.eat(elf_index, rng)?;
+            }
+
+            if self.elves[elf_index].thirst as i32 <= DRINK_AT as i32 {
+                self.drink(elf_index)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// hurt elves for every critical need as if in a fight, and heal those with none
+    fn tick_health(&mut self, tick: u64) -> Result<(), GameError> {
+        if !due(tick, CRITICAL_DAMAGE_TICKS) {
+            return Ok(());
+        }
+
+        for elf in &mut self.elves {
+            let critical = critical_needs(elf).len() as i32;
+
+            if critical > 0 {
+                elf.health -= critical * CRITICAL_DAMAGE;
+            } else {
+                elf.health = (elf.health + RECOVERY).min(MAX_HEALTH);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// eat one piece of food from the stockpile. false if there is none
+    fn eat(&mut self, elf_index: usize, rng: &mut GameRng) -> Result<bool, GameError> {
+        let food = self
+            .stockpile
+            .find(|object| matches!(object.object_type, ObjectType::Food { .. }))
+            .cloned();
+
+        let Some(food) = food else {
+            return Ok(false);
+        };
+
+        self.stockpile.take(&food, 1);
+
+        let hunger_power = match food.object_type {
+            ObjectType::Food { hunger_power, .. } => hunger_power.max(0.0),
+            _ => unreachable!(),
+        };
+
+        // restore floor(hunger_power), and the decimal chance of one more
+        let mut restored = hunger_power.floor() as u32;
+
+        if rng.gen_range(0.0..1.0) < hunger_power.fract() {
+            restored += 1;
+        }
+
+        let elf = &mut self.elves[elf_index];
+        elf.hunger = elf.hunger.raise(restored);
+
+        Ok(true)
+    }
+
+    /// drink one water from the stockpile, fully quenching thirst. false if there is none
+    fn drink(&mut self, elf_index: usize) -> Result<bool, GameError> {
+        let water = self
+            .stockpile
+            .find(|object| object.object_type == ObjectType::Water)
+            .cloned();
+
+        let Some(water) = water else {
+            return Ok(false);
+        };
+
+        self.stockpile.take(&water, 1);
+        self.elves[elf_index].thirst = AttributeLevel::Excellent;
+
+        Ok(true)
+    }
+}
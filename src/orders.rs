@@ -0,0 +1,120 @@
+use crate::{defs::*, game::*, impls::*};
+
+pub trait StandingOrders {
+    fn add_order(&mut self, task: Task, kind: OrderKind) -> u32;
+    fn remove_order(&mut self, order: u32) -> Result<StandingOrder, GameError>;
+    fn pause_order(&mut self, order: u32) -> Result<(), GameError>;
+    fn resume_order(&mut self, order: u32) -> Result<(), GameError>;
+    fn pause_orders(&mut self);
+    fn resume_orders(&mut self);
+    fn order_due(&self, order: &StandingOrder) -> bool;
+    fn issue_orders(&mut self) -> Result<Vec<u32>, GameError>;
+}
+
+impl StandingOrders for Stronghold {
+    /// add a standing order. it queues its first task on the next tick it is due
+    fn add_order(&mut self, task: Task, kind: OrderKind) -> u32 {
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+
+        self.standing_orders.push(StandingOrder {
+            id,
+            task,
+            kind,
+            paused: false,
+            current_task: None,
+        });
+
+        id
+    }
+
+    /// remove a standing order. the task it last queued is left to finish
+    fn remove_order(&mut self, order: u32) -> Result<StandingOrder, GameError> {
+        let position = self
+            .standing_orders
+            .iter()
+            .position(|o| o.id == order)
+            .ok_or_else(|| GameError::NoSuchOrderError(format!("No standing order {}", order)))?;
+
+        Ok(self.standing_orders.remove(position))
+    }
+
+    fn pause_order(&mut self, order: u32) -> Result<(), GameError> {
+        order_mut(self, order)?.paused = true;
+
+        Ok(())
+    }
+
+    fn resume_order(&mut self, order: u32) -> Result<(), GameError> {
+        order_mut(self, order)?.paused = false;
+
+        Ok(())
+    }
+
+    fn pause_orders(&mut self) {
+        self.orders_paused = true;
+    }
+
+    fn resume_orders(&mut self) {
+        self.orders_paused = false;
+    }
+
+    /// whether an order should queue another task: it isn't paused, its last task is out of the way, a building is free for it and (for stock orders) the stockpile is short
+    fn order_due(&self, order: &StandingOrder) -> bool {
+        if self.orders_paused || order.paused {
+            return false;
+        }
+
+        let pending = order.current_task.is_some_and(|task_id| {
+            self.task_queue
+                .iter()
+                .chain(self.active_tasks.iter())
+                .any(|task| task.id == task_id)
+        });
+
+        // one task per order at a time, and none while every building it could use is busy, so orders can't flood the queue
+        if pending || self
+                .free_building(order.task.required_building, order.task.required_level)
+                .is_none() {
+            return false;
+        }
+
+        match &order.kind {
+            OrderKind::Repeat => true,
+            OrderKind::KeepStocked { object, target } => self.stockpile.count(object) < *target,
+        }
+    }
+
+    /// queue a task for every standing order that is due, returning the new task IDs
+    fn issue_orders(&mut self) -> Result<Vec<u32>, GameError> {
+        let mut issued = Vec::new();
+
+        for i in 0..self.standing_orders.len() {
+            if !self.order_due(&self.standing_orders[i]) {
+                continue;
+            }
+
+            let task_id = self.next_task_id;
+
+            // an order whose task can't be done here at all waits until it can
+            match self.new_task(self.standing_orders[i].task.clone()) {
+                Ok(_) => {}
+                Err(GameError::NoSuitableBuildingError(_)) | Err(GameError::NoSuitableElfError(_)) => continue,
+                Err(error) => return Err(error),
+            }
+
+            self.standing_orders[i].current_task = Some(task_id);
+            issued.push(task_id);
+        }
+
+        Ok(issued)
+    }
+}
+
+fn order_mut(stronghold: &mut Stronghold, order: u32) -> Result<&mut StandingOrder, GameError> {
+    stronghold
+        .standing_orders
+        .iter_mut()
+        .find(|o| o.id == order)
+        .ok_or_else(|| GameError::NoSuchOrderError(format!("No standing order {}", order)))
+}
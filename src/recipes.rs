@@ -0,0 +1,107 @@
+use crate::{defs::*, game::*, impls::*};
+use std::{fs, path::Path};
+
+/// recipes that ship with the game
+pub const DEFAULT_RECIPES: &str = include_str!("../data/recipes.json");
+
+/// Every recipe the game knows, loaded from a JSON list of recipes
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecipeBook {
+    pub recipes: Vec<Recipe>,
+}
+
+impl Default for RecipeBook {
+    fn default() -> Self {
+        RecipeBook::from_json(DEFAULT_RECIPES).expect("built in recipes are valid")
+    }
+}
+
+impl RecipeBook {
+    /// read a recipe book, checking every recipe makes sense
+    pub fn from_json(json: &str) -> Result<Self, GameError> {
+        let recipes: Vec<Recipe> =
+            serde_json::from_str(json).map_err(|error| GameError::LoadError(error.to_string()))?;
+
+        for (i, recipe) in recipes.iter().enumerate() {
+            let invalid = |reason: &str| GameError::LoadError(format!("Recipe {}: {}", recipe.name, reason));
+
+            if recipes[..i].iter().any(|other| other.name == recipe.name) {
+                return Err(invalid("name is used more than once"));
+            }
+
+            if recipe.outputs.is_empty() {
+                return Err(invalid("makes nothing"));
+            }
+
+            if recipe.inputs.iter().chain(recipe.outputs.iter()).any(|stack| stack.count == 0) {
+                return Err(invalid("has a stack of 0"));
+            }
+
+            if recipe.roles.is_empty() || recipe.roles.iter().any(|requirement| requirement.workers == 0) {
+                return Err(invalid("needs at least one worker for each role"));
+            }
+
+            if recipe.min_level == 0 {
+                return Err(invalid("buildings start at level 1"));
+            }
+        }
+
+        Ok(RecipeBook { recipes })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, GameError> {
+        RecipeBook::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn recipe(&self, name: &str) -> Result<&Recipe, GameError> {
+        self.recipes
+            .iter()
+            .find(|recipe| recipe.name == name)
+            .ok_or_else(|| GameError::NoSuchRecipeError(format!("No recipe {}", name)))
+    }
+
+    /// every recipe made at a type of building
+    pub fn for_building(&self, building_type: BuildingType) -> Vec<&Recipe> {
+        self.recipes
+            .iter()
+            .filter(|recipe| recipe.building == building_type)
+            .collect()
+    }
+}
+
+pub trait Crafting {
+    fn recipe_task(&self, recipe: &Recipe) -> Result<Task, GameError>;
+    fn craft(&mut self, recipe: &Recipe) -> Result<bool, GameError>;
+}
+
+impl Crafting for Stronghold {
+    /// a task that makes a recipe, if the stronghold has a building that can make it
+    fn recipe_task(&self, recipe: &Recipe) -> Result<Task, GameError> {
+        if !self
+            .buildings
+            .iter()
+            .any(|building| building.building_type == recipe.building && building.level >= recipe.min_level)
+        {
+            return Err(GameError::NoSuitableBuildingError(format!(
+                "{} needs a {:?} of level {}",
+                recipe.name, recipe.building, recipe.min_level
+            )));
+        }
+
+        let mut task = Task::new(recipe.name.clone(), recipe.duration, recipe.building, recipe.roles.clone());
+        task.required_level = recipe.min_level;
+        task.required_objects = recipe.inputs.clone();
+        task.produced_objects = recipe.outputs.clone();
+
+        Ok(task)
+    }
+
+    /// queue a recipe to be made
+    /// true - task assigned to elves
+    /// false - task added to task queue
+    fn craft(&mut self, recipe: &Recipe) -> Result<bool, GameError> {
+        let task = self.recipe_task(recipe)?;
+
+        self.new_task(task)
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{defs::*, game::*, migrate::*};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// directory save slots are kept in unless told otherwise
+pub const DEFAULT_SAVE_DIRECTORY: &str = "saves";
+
+/// file extension of every save slot
+pub const SAVE_EXTENSION: &str = "json";
+
+/// Metadata written as the first line of every save slot, so slots can be listed without deserializing the whole world
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaveHeader {
+    /// name of the saved world
+    pub world_name: String,
+
+    /// in game tick the world was saved on
+    pub tick: u64,
+
+    /// number of colonies in the world
+    pub colony_count: u32,
+
+    /// time of saving in seconds since the unix epoch
+    pub saved_at: u64,
+
+    /// version of the game that wrote the save
+    pub game_version: String,
+
+    /// save schema version of the world on the next line (slots written before versioning are version 1)
+    #[serde(default = "first_slot_version")]
+    pub version: u32,
+}
+
+fn first_slot_version() -> u32 {
+    1
+}
+
+/// A save slot on disk and its header
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveSlot {
+    /// name of slot (the file name without extension)
+    pub name: String,
+
+    /// header read from the first line of the slot
+    pub header: SaveHeader,
+}
+
+/// A directory of save slots
+#[derive(Debug, Clone)]
+pub struct SaveDirectory {
+    /// path of the directory, created on first save
+    pub path: PathBuf,
+}
+
+impl Default for SaveDirectory {
+    fn default() -> Self {
+        SaveDirectory {
+            path: PathBuf::from(DEFAULT_SAVE_DIRECTORY),
+        }
+    }
+}
+
+pub trait SaveSlots {
+    fn save(&self, slot: &str, world: &World) -> Result<SaveHeader, GameError>;
+    fn load(&self, slot: &str) -> Result<World, GameError>;
+    fn header(&self, slot: &str) -> Result<SaveHeader, GameError>;
+    fn list(&self) -> Result<Vec<SaveSlot>, GameError>;
+    fn rename(&self, slot: &str, new_name: &str) -> Result<(), GameError>;
+    fn duplicate(&self, slot: &str, new_name: &str) -> Result<(), GameError>;
+    fn delete(&self, slot: &str) -> Result<(), GameError>;
+}
+
+impl SaveDirectory {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        SaveDirectory { path: path.into() }
+    }
+
+    /// path of the file backing a slot, rejecting names that would escape the directory
+    fn slot_path(&self, slot: &str) -> Result<PathBuf, GameError> {
+        if slot.is_empty()
+            || slot.starts_with('.')
+            || slot.contains(|c: char| c == '/' || c == '\\' || c.is_control())
+        {
+            return Err(GameError::SaveError(format!("Invalid save slot name: {:?}", slot)));
+        }
+
+        Ok(self.path.join(format!("{}.{}", slot, SAVE_EXTENSION)))
+    }
+
+    /// path of an existing slot
+    fn existing_slot_path(&self, slot: &str) -> Result<PathBuf, GameError> {
+        let path = self.slot_path(slot)?;
+
+        if !path.is_file() {
+            return Err(GameError::SlotNotFoundError(slot.to_string()));
+        }
+
+        Ok(path)
+    }
+
+    /// path of a slot that must not exist yet
+    fn free_slot_path(&self, slot: &str) -> Result<PathBuf, GameError> {
+        let path = self.slot_path(slot)?;
+
+        if path.exists() {
+            return Err(GameError::SlotExistsError(slot.to_string()));
+        }
+
+        Ok(path)
+    }
+}
+
+impl SaveSlots for SaveDirectory {
+    /// write the world to a slot, overwriting it if it already exists
+    fn save(&self, slot: &str, world: &World) -> Result<SaveHeader, GameError> {
+        let path = self.slot_path(slot)?;
+
+        let header = SaveHeader {
+            world_name: world.name.clone(),
+            tick: world.tick,
+            colony_count: world.colonies.len() as u32,
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            game_version: env!("CARGO_PKG_VERSION").to_string(),
+            version: SAVE_VERSION,
+        };
+
+        fs::create_dir_all(&self.path)?;
+
+        // write to a temporary file first so a failed save never clobbers the old one
+        let temporary = path.with_extension("tmp");
+        let mut file = File::create(&temporary)?;
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        writeln!(file, "{}", serde_json::to_string(world)?)?;
+        file.sync_all()?;
+        fs::rename(&temporary, &path)?;
+
+        Ok(header)
+    }
+
+    fn load(&self, slot: &str) -> Result<World, GameError> {
+        load_file(&self.existing_slot_path(slot)?)
+    }
+
+    /// read only the header line of a slot
+    fn header(&self, slot: &str) -> Result<SaveHeader, GameError> {
+        let path = self.existing_slot_path(slot)?;
+        let mut reader = BufReader::new(File::open(&path)?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+
+        if let Ok(header) = serde_json::from_str(&line) {
+            return Ok(header);
+        }
+
+        // headerless saves have to be read in full to describe them
+        let (version, world) = read_file(&path)?;
+        let world = parse_world(world, version)?;
+
+        Ok(SaveHeader {
+            world_name: world.name.clone(),
+            tick: world.tick,
+            colony_count: world.colonies.len() as u32,
+            saved_at: fs::metadata(&path)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            game_version: "unknown".to_string(),
+            version,
+        })
+    }
+
+    /// list all readable slots, most recently saved first
+    fn list(&self) -> Result<Vec<SaveSlot>, GameError> {
+        if !self.path.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut slots = Vec::new();
+
+        for entry in fs::read_dir(&self.path)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|extension| extension.to_str()) != Some(SAVE_EXTENSION) {
+                continue;
+            }
+
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+
+            // unreadable files are not slots, but shouldn't hide the rest
+            if let Ok(header) = self.header(&name) {
+                slots.push(SaveSlot { name, header });
+            }
+        }
+
+        slots.sort_by(|a, b| b.header.saved_at.cmp(&a.header.saved_at).then(a.name.cmp(&b.name)));
+
+        Ok(slots)
+    }
+
+    fn rename(&self, slot: &str, new_name: &str) -> Result<(), GameError> {
+        let from = self.existing_slot_path(slot)?;
+        let to = self.free_slot_path(new_name)?;
+
+        fs::rename(from, to)?;
+
+        Ok(())
+    }
+
+    fn duplicate(&self, slot: &str, new_name: &str) -> Result<(), GameError> {
+        let from = self.existing_slot_path(slot)?;
+        let to = self.free_slot_path(new_name)?;
+
+        fs::copy(from, to)?;
+
+        Ok(())
+    }
+
+    fn delete(&self, slot: &str) -> Result<(), GameError> {
+        fs::remove_file(self.existing_slot_path(slot)?)?;
+
+        Ok(())
+    }
+}
+
+/// read a save file, either a slot (header line then world line) or a headerless version 0 world.json
+pub fn load_file(path: &Path) -> Result<World, GameError> {
+    let (version, world) = read_file(path)?;
+
+    parse_world(world, version)
+}
+
+/// split a save file into its schema version and raw world document
+fn read_file(path: &Path) -> Result<(u32, Value), GameError> {
+    let mut buffer = String::new();
+    File::open(path)?.read_to_string(&mut buffer)?;
+
+    let (first, rest) = buffer.split_once('\n').unwrap_or((&buffer, ""));
+
+    let load_error = |error: serde_json::Error| GameError::LoadError(error.to_string());
+
+    match serde_json::from_str::<SaveHeader>(first) {
+        Ok(header) => Ok((header.version, serde_json::from_str(rest).map_err(load_error)?)),
+        Err(_) => Ok((0, serde_json::from_str(&buffer).map_err(load_error)?)),
+    }
+}
+
+/// bring a raw world document up to date and deserialize it
+fn parse_world(world: Value, version: u32) -> Result<World, GameError> {
+    serde_json::from_value(migrate(world, version)?).map_err(|error| GameError::LoadError(error.to_string()))
+}
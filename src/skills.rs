@@ -0,0 +1,84 @@
+use crate::defs::*;
+
+/// percent of a task's base duration an Average elf takes. every level of ability above or below moves it by ABILITY_STEP
+pub const BASE_DURATION_PERCENT: i32 = 100;
+
+/// percent of duration gained or lost per level of ability away from Average
+pub const ABILITY_STEP: i32 = 20;
+
+/// percent of duration gained or lost per level of intelligence away from Average
+pub const INTELLIGENCE_STEP: i32 = 10;
+
+/// experience needed to go up from a level is this times the level's value
+pub const EXPERIENCE_PER_LEVEL: u32 = 2000;
+
+/// the workers' average ability in the roles they cover, and their average intelligence
+pub fn crew_ability(task: &Task, elves: &[Elf]) -> (i32, i32) {
+    let crew = task
+        .workers
+        .iter()
+        .filter_map(|worker| {
+            let elf = elves.iter().find(|elf| elf.id == worker.elf)?;
+            let ability = elf
+                .roles
+                .iter()
+                .find(|r| r.role == worker.role)
+                .map(|r| r.ability)
+                .unwrap_or(AttributeLevel::Terrible);
+
+            Some((ability as i32, elf.intelligence as i32))
+        })
+        .collect::<Vec<(i32, i32)>>();
+
+    if crew.is_empty() {
+        return (AttributeLevel::Average as i32, AttributeLevel::Average as i32);
+    }
+
+    let count = crew.len() as i32;
+
+    (
+        crew.iter().map(|(ability, _)| ability).sum::<i32>() / count,
+        crew.iter().map(|(_, intelligence)| intelligence).sum::<i32>() / count,
+    )
+}
+
+/// how many ticks a task takes its workers - skilled, clever crews are quicker
+pub fn task_duration(task: &Task, elves: &[Elf]) -> u32 {
+    let average = AttributeLevel::Average as i32;
+    let (ability, intelligence) = crew_ability(task, elves);
+
+    let percent = BASE_DURATION_PERCENT
+        - (ability - average) * ABILITY_STEP
+        - (intelligence - average) * INTELLIGENCE_STEP;
+
+    (task.duration as u64 * percent as u64 / 100).max(1) as u32
+}
+
+/// experience needed to go up from a level
+pub fn experience_to_next(level: AttributeLevel) -> u32 {
+    EXPERIENCE_PER_LEVEL * level as u32
+}
+
+/// credit an elf with experience in the role it covered on a finished task (one point per tick of base duration), promoting it as thresholds are passed
+pub fn gain_experience(elf: &mut Elf, role: &Role, experience: u32) {
+    let Some(role_ability) = elf.roles.iter_mut().find(|r| r.role == *role) else {
+        return;
+    };
+
+    if role_ability.ability == AttributeLevel::Excellent {
+        return;
+    }
+
+    role_ability.experience += experience;
+
+    while role_ability.ability != AttributeLevel::Excellent
+        && role_ability.experience >= experience_to_next(role_ability.ability)
+    {
+        role_ability.experience -= experience_to_next(role_ability.ability);
+        role_ability.ability = role_ability.ability.raise(1);
+    }
+
+    if role_ability.ability == AttributeLevel::Excellent {
+        role_ability.experience = 0;
+    }
+}
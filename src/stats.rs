@@ -0,0 +1,185 @@
+use crate::{defs::*, durability::*, game::*, groups::*, impls::*, needs::*};
+
+/// highest level of any stronghold stat
+pub const MAX_STAT_LEVEL: u32 = 5;
+
+/// elves each level of size makes room for
+pub const ELVES_PER_SIZE: u32 = 12;
+
+/// buildings each level of size makes room for
+pub const BUILDINGS_PER_SIZE: u32 = 6;
+
+/// ticks to travel between two strongholds at movement level 1 (every stronghold is the same distance from the next)
+pub const BASE_TRAVEL_TICKS: u64 = TICKS_PER_HOUR * 6;
+
+/// defence each level of a wall gives
+pub const WALL_DEFENCE: u32 = 10;
+
+/// defence each level of a tower gives
+pub const TOWER_DEFENCE: u32 = 15;
+
+/// what a stronghold's walls and towers are worth, at so much a level of each: weakened by damage and strengthened by fortification (a quarter more each level)
+pub fn walls_and_towers(stronghold: &Stronghold, per_wall: u32, per_tower: u32) -> u32 {
+    let total = stronghold
+        .buildings
+        .iter()
+        .map(|building| {
+            let per_level = match building.building_type {
+                BuildingType::Wall => per_wall,
+                BuildingType::Tower => per_tower,
+                _ => 0,
+            };
+
+            per_level * building.level * building.health / max_health(building.level)
+        })
+        .sum::<u32>();
+
+    total * (stronghold.stats.fortification + 3) / 4
+}
+
+/// what the next level of a stat costs
+pub fn stat_cost(stat: StrongholdStat, level: u32) -> Cost {
+    match stat {
+        StrongholdStat::Size => Cost::resource(ResourceType::Wood, 10 * level).and_resource(ResourceType::Stone, 5 * level),
+        StrongholdStat::Movement => Cost::resource(ResourceType::Wood, 5 * level).and_resource(ResourceType::Animal, 2 * level),
+        StrongholdStat::Fortification => {
+            Cost::resource(ResourceType::Stone, 10 * level).and_resource(ResourceType::Iron, 3 * level)
+        }
+    }
+}
+
+pub trait Stats {
+    fn stat(&self, stat: StrongholdStat) -> u32;
+    fn upgrade_stat(&mut self, stat: StrongholdStat) -> Result<u32, GameError>;
+    fn elf_capacity(&self) -> u32;
+    fn building_capacity(&self) -> u32;
+    fn travel_ticks(&self) -> u64;
+    fn defence(&self) -> u32;
+    fn recruit(&mut self, elf: Elf) -> Result<u32, GameError>;
+}
+
+impl Stats for Stronghold {
+    fn stat(&self, stat: StrongholdStat) -> u32 {
+        match stat {
+            StrongholdStat::Size => self.stats.size,
+            StrongholdStat::Movement => self.stats.movement,
+            StrongholdStat::Fortification => self.stats.fortification,
+        }
+    }
+
+    /// pay for and raise a stat by a level, returning the new level
+    fn upgrade_stat(&mut self, stat: StrongholdStat) -> Result<u32, GameError> {
+        let level = self.stat(stat) + 1;
+
+        if level > MAX_STAT_LEVEL {
+            return Err(GameError::CapacityError(format!("{:?} is already at level {}", stat, MAX_STAT_LEVEL)));
+        }
+
+        self.stockpile.pay(&stat_cost(stat, level))?;
+
+        match stat {
+            StrongholdStat::Size => self.stats.size = level,
+            StrongholdStat::Movement => self.stats.movement = level,
+            StrongholdStat::Fortification => self.stats.fortification = level,
+        }
+
+        Ok(level)
+    }
+
+    fn elf_capacity(&self) -> u32 {
+        self.stats.size * ELVES_PER_SIZE
+    }
+
+    /// buildings the stronghold has room for, counting those still being put up
+    fn building_capacity(&self) -> u32 {
+        self.stats.size * BUILDINGS_PER_SIZE
+    }
+
+    /// ticks for an elf to travel from this stronghold to another. each level of movement takes a share off (level 1 is the full trip, level 5 a third)
+    fn travel_ticks(&self) -> u64 {
+        BASE_TRAVEL_TICKS * 2 / (self.stats.movement as u64 + 1)
+    }
+
+    /// defence from walls and towers
+    fn defence(&self) -> u32 {
+        walls_and_towers(self, WALL_DEFENCE, TOWER_DEFENCE)
+    }
+
+    /// take an elf in, if there is room for it, returning its ID
+    fn recruit(&mut self, elf: Elf) -> Result<u32, GameError> {
+        if self.elves.len() as u32 >= self.elf_capacity() {
+            return Err(GameError::CapacityError(format!("{} has no room for more elves", self.name)));
+        }
+
+        Ok(self.add_elf(elf))
+    }
+}
+
+/// set an elf off from one of a colony's strongholds to another
+pub fn send_elf(colony: &mut Colony, from: usize, elf: u32, destination: usize, tick: u64) -> Result<u64, GameError> {
+    if from == destination || destination >= colony.strongholds.len() {
+        return Err(GameError::NoSuitableBuildingError(format!("No stronghold {} to send to", destination)));
+    }
+
+    let origin = colony
+        .strongholds
+        .get_mut(from)
+        .ok_or_else(|| GameError::NoSuitableBuildingError(format!("No stronghold {}", from)))?;
+
+    let index = origin
+        .elf_index(elf)
+        .ok_or_else(|| GameError::NoSuitableElfError(format!("No elf {}", elf)))?;
+
+    let arrives = tick + origin.travel_ticks();
+    let elf = leave(origin, index);
+
+    colony.travellers.push(Traveller {
+        elf,
+        destination: colony.strongholds[destination].name.clone(),
+        arrives,
+    });
+
+    Ok(arrives)
+}
+
+/// take an elf off a stronghold's roster, out of its work groups and off its task
+pub fn leave(stronghold: &mut Stronghold, index: usize) -> Elf {
+    let mut elf = stronghold.elves.remove(index);
+
+    for group in &mut stronghold.work_groups {
+        group.members.retain(|member| *member != elf.id);
+    }
+
+    if let Some(task) = elf.task.take().and_then(|task_id| stronghold.stop_task(task_id)) {
+        stronghold.task_queue.insert(0, task);
+    }
+
+    elf
+}
+
+/// take in every traveller that has arrived. those whose stronghold is full wait on the road until there is room, and those whose stronghold is gone head for the colony's first (or wait until it has one)
+pub fn arrivals(colony: &mut Colony, tick: u64) {
+    let mut waiting = Vec::new();
+
+    for mut traveller in std::mem::take(&mut colony.travellers) {
+        if !colony.strongholds.iter().any(|stronghold| stronghold.name == traveller.destination) {
+            if let Some(first) = colony.strongholds.first() {
+                traveller.destination = first.name.clone();
+            }
+        }
+
+        let destination = colony
+            .strongholds
+            .iter_mut()
+            .find(|stronghold| stronghold.name == traveller.destination);
+
+        match destination {
+            Some(stronghold) if traveller.arrives <= tick && (stronghold.elves.len() as u32) < stronghold.elf_capacity() => {
+                stronghold.add_elf(traveller.elf);
+            }
+            _ => waiting.push(traveller),
+        }
+    }
+
+    colony.travellers = waiting;
+}